@@ -0,0 +1,83 @@
+// Beat clock shared with the game, and transitions that wait for the next beat or bar
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+use crate::audio::sound::{SongId, SoundId};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Quantize {
+    #[default]
+    Immediate,
+    Beat,
+    Bar,
+}
+
+impl Quantize {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "now" => Some(Self::Immediate),
+            "beat" => Some(Self::Beat),
+            "bar" => Some(Self::Bar),
+            _ => None,
+        }
+    }
+
+    // First beat on or after `beat` where a transition can start
+    pub fn next_boundary(&self, beat: f64, bar_length: usize) -> f64 {
+        match self {
+            Self::Immediate => beat,
+            Self::Beat => beat.ceil(),
+            Self::Bar => (beat / bar_length as f64).ceil() * bar_length as f64,
+        }
+    }
+}
+
+pub enum MusicEvent {
+    SwitchSong {
+        song: SongId,
+        quantize: Quantize,
+    },
+    Stinger {
+        sound: SoundId,
+        volume: f32,
+        quantize: Quantize,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BeatPosition {
+    pub playing: bool,
+    pub beat: f64,        // beats since the song started
+    pub bar: u32,         // bars since the song started
+    pub beat_in_bar: f64, // 0 up to the bar length, the fraction is the phase within the beat
+    pub tempo: f64,       // beats per second
+}
+
+// Written by the audio thread after every rendered chunk
+#[derive(Default)]
+pub struct BeatClock {
+    playing: AtomicBool,
+    beat: AtomicU64, // f64 bits
+    tempo: AtomicU64,
+    bar_length: AtomicU32,
+}
+
+impl BeatClock {
+    pub fn publish(&self, playing: bool, beat: f64, tempo: f64, bar_length: usize) {
+        self.playing.store(playing, Ordering::Relaxed);
+        self.beat.store(beat.to_bits(), Ordering::Relaxed);
+        self.tempo.store(tempo.to_bits(), Ordering::Relaxed);
+        self.bar_length.store(bar_length as u32, Ordering::Relaxed);
+    }
+
+    pub fn position(&self) -> BeatPosition {
+        let beat = f64::from_bits(self.beat.load(Ordering::Relaxed));
+        let bar_length = self.bar_length.load(Ordering::Relaxed).max(1) as f64;
+        BeatPosition {
+            playing: self.playing.load(Ordering::Relaxed),
+            beat,
+            bar: (beat / bar_length) as u32,
+            beat_in_bar: beat % bar_length,
+            tempo: f64::from_bits(self.tempo.load(Ordering::Relaxed)),
+        }
+    }
+}
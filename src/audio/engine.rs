@@ -0,0 +1,258 @@
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+use super::{AudioDiagnostics, AudioSettings};
+use crate::audio::clock::{BeatClock, MusicEvent, Quantize};
+use crate::audio::layers::MusicState;
+use crate::audio::mixer::{Limiter, Mixer, SfxEvent, Voice};
+use crate::audio::sound::{self, FilterState, SongId, SoundEffects, SoundId, play_music};
+use crate::audio::synth::{Synth, SynthEvent};
+use crate::audio::wav::Sample;
+
+const MAX_STINGERS: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+struct PendingStinger {
+    sound: SoundId,
+    volume: f32,
+    beat: f64,
+}
+
+// All synthesis state, independent of the output device so it can also render offline
+pub struct AudioEngine {
+    pub sample_rate: u32,
+    pub time: f64,
+    last_time: f64,
+
+    pub(crate) settings: AudioSettings,
+    limiter: Limiter,
+
+    synth: Synth,
+
+    songs: Vec<sound::Music>,
+    song: usize,
+    pub music_state: MusicState,
+    pub clock: Arc<BeatClock>,
+    pub(crate) diagnostics: Arc<AudioDiagnostics>,
+
+    // Transitions waiting for the next beat or bar of the current song
+    pending_song: Option<(usize, f64)>,
+    pending_stingers: [Option<PendingStinger>; MAX_STINGERS],
+
+    pub soundeffects: SoundEffects,
+    mixer: Mixer,
+}
+
+impl AudioEngine {
+    pub(crate) fn new(sample_rate: u32, settings: AudioSettings, songs: Vec<sound::Music>) -> Self {
+        assert!(
+            !songs.is_empty(),
+            "the audio engine needs at least one song"
+        );
+        let soundeffects = SoundEffects::new(sample_rate);
+
+        Self {
+            sample_rate,
+            time: 0.0,
+            last_time: -1.0 / sample_rate as f64,
+
+            settings,
+            limiter: Limiter::new(),
+
+            synth: Synth::new(),
+
+            songs,
+            song: 0,
+            music_state: MusicState::default(),
+            clock: Arc::new(BeatClock::default()),
+            diagnostics: Arc::new(AudioDiagnostics::default()),
+
+            pending_song: None,
+            pending_stingers: [None; MAX_STINGERS],
+
+            soundeffects,
+            mixer: Mixer::new(),
+        }
+    }
+
+    // Used when the output device runs at a different rate, samples get resampled again
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.soundeffects = SoundEffects::new(sample_rate);
+        self.last_time = self.time - 1.0 / sample_rate as f64;
+    }
+
+    pub fn handle_synth(&mut self, synth_event: SynthEvent) {
+        self.synth.handle(synth_event, self.time);
+    }
+
+    pub fn handle_sfx(&mut self, sfx_event: SfxEvent) {
+        match sfx_event {
+            SfxEvent::Play { sound, volume, pan } => {
+                self.mixer.play(Voice {
+                    sound,
+                    start: self.time,
+                    volume: volume as f64,
+                    pan: pan as f64,
+                    filter_state: FilterState::default(),
+                });
+            }
+            SfxEvent::Stop(sound) => self.mixer.stop(sound),
+        }
+    }
+
+    pub fn handle_music_event(&mut self, music_event: MusicEvent) {
+        let music = &self.songs[self.song];
+        let boundary = |quantize: Quantize| quantize.next_boundary(music.beat, music.bar_length);
+
+        match music_event {
+            MusicEvent::SwitchSong { song, quantize } => {
+                let song = song.0 as usize;
+                if song < self.songs.len() && song != self.song {
+                    self.pending_song = Some((song, boundary(quantize)));
+                }
+            }
+            MusicEvent::Stinger {
+                sound,
+                volume,
+                quantize,
+            } => {
+                let beat = boundary(quantize);
+                if let Some(slot) = self.pending_stingers.iter_mut().find(|s| s.is_none()) {
+                    *slot = Some(PendingStinger {
+                        sound,
+                        volume,
+                        beat,
+                    });
+                }
+            }
+        }
+    }
+
+    pub fn current_song(&self) -> SongId {
+        SongId(self.song as u32)
+    }
+
+    // Starts the transitions whose beat has come, a silent song starts them right away
+    fn start_transitions(&mut self, t: f64) {
+        let music = &self.songs[self.song];
+        let (beat, playing) = (music.beat, music.is_playing());
+
+        if let Some((song, at)) = self.pending_song
+            && (!playing || beat >= at)
+        {
+            self.pending_song = None;
+            self.songs[self.song].reset();
+            self.song = song;
+
+            // The new song starts on the boundary at the volumes the game state asks for
+            let music = &mut self.songs[self.song];
+            music.update_layers(&self.music_state, t);
+            for layer in music.layers.iter_mut() {
+                layer.snap();
+            }
+
+            // Stingers were waiting for the old song, play them together with the new one
+            for stinger in self.pending_stingers.iter_mut().flatten() {
+                stinger.beat = 0.0;
+            }
+        }
+
+        let beat = self.songs[self.song].beat;
+        for slot in self.pending_stingers.iter_mut() {
+            if let Some(stinger) = slot
+                && (!playing || beat >= stinger.beat)
+            {
+                self.mixer.play(Voice {
+                    sound: stinger.sound,
+                    start: t,
+                    volume: stinger.volume as f64,
+                    pan: 0.0,
+                    filter_state: FilterState::default(),
+                });
+                *slot = None;
+            }
+        }
+    }
+
+    // Fills an interleaved buffer, the first two channels are left and right
+    pub fn render(&mut self, data: &mut [f32], channels: usize) {
+        let sample_duration = 1.0 / self.sample_rate as f64;
+        let chunk_time = (data.len() / channels) as f64 / self.sample_rate as f64;
+
+        self.songs[self.song].update_layers(&self.music_state, self.time);
+
+        let mut t = self.time;
+
+        for frame in data.chunks_exact_mut(channels) {
+            if ((t - self.last_time) - sample_duration).abs() > 0.0000000001 {
+                self.diagnostics
+                    .timing_errors
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+            self.last_time = t;
+
+            self.start_transitions(t);
+            // Music bus, the synth is played along with the music
+            let music = play_music(t, &mut self.songs[self.song], sample_duration)
+                + self.synth.play(t, sample_duration);
+            let music = music as f32 * self.settings.music_volume;
+
+            // Sound effect bus
+            let (sfx_left, sfx_right) =
+                self.mixer
+                    .mix(t, &self.soundeffects.sounds, sample_duration);
+            let sfx_left = sfx_left as f32 * self.settings.sfx_volume;
+            let sfx_right = sfx_right as f32 * self.settings.sfx_volume;
+
+            // Master bus
+            let (left, right) = self.limiter.process(
+                (music + sfx_left) * self.settings.master_volume,
+                (music + sfx_right) * self.settings.master_volume,
+                sample_duration as f32,
+            );
+
+            if channels == 1 {
+                frame[0] = (left + right) * 0.5;
+            } else {
+                // left and right channel
+                frame[0] = left;
+                frame[1] = right;
+
+                // Output the center mix for all other channels for now
+                for sample in frame[2..].iter_mut() {
+                    *sample = (left + right) * 0.5;
+                }
+            }
+
+            t += sample_duration;
+        }
+
+        self.time += chunk_time;
+        assert!((t - self.time).abs() < 0.00001);
+
+        let music = &self.songs[self.song];
+        self.clock.publish(
+            music.is_playing(),
+            music.beat,
+            music.tempo,
+            music.bar_length,
+        );
+    }
+
+    // Renders the next `seconds` of audio without an output device
+    pub fn render_offline(&mut self, seconds: f64) -> Sample {
+        let frame_count = (seconds * self.sample_rate as f64) as usize;
+        let mut data = vec![0.0; frame_count * 2];
+
+        // Render in device sized chunks so timing behaves the same as the live stream
+        for chunk in data.chunks_mut(512 * 2) {
+            self.render(chunk, 2);
+        }
+
+        Sample {
+            sample_rate: self.sample_rate,
+            frames: data.chunks_exact(2).map(|s| [s[0], s[1]]).collect(),
+        }
+    }
+}
@@ -0,0 +1,241 @@
+// Adaptive music, `layer` rules in the music file map the game state to track volumes
+use crate::audio::clock::Quantize;
+
+const COLOR_NAMES: [&str; 3] = ["red", "green", "blue"];
+
+// What the music reacts to, sent by the game every frame
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MusicState {
+    pub masks_owned: [bool; 3], // red, green, blue
+    pub active_color: [bool; 3],
+    pub danger: f32, // 0 when safe, 1 when an enemy is right next to the player
+    pub dead: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Condition {
+    Owned(usize),
+    Active(usize),
+    DangerAbove(f32),
+    DangerBelow(f32),
+    Dead,
+}
+
+impl Condition {
+    // Returns the condition and whether it has to hold, `!` negates it
+    fn parse(token: &str) -> Option<(Self, bool)> {
+        if let Some(token) = token.strip_prefix('!') {
+            return Self::parse(token).map(|(condition, expected)| (condition, !expected));
+        }
+        let condition = if let Some(value) = token.strip_prefix("danger>") {
+            Self::DangerAbove(value.parse().ok()?)
+        } else if let Some(value) = token.strip_prefix("danger<") {
+            Self::DangerBelow(value.parse().ok()?)
+        } else if let Some(color) = token.strip_prefix("active=") {
+            Self::Active(COLOR_NAMES.iter().position(|&c| c == color)?)
+        } else if token == "dead" {
+            Self::Dead
+        } else if token == "alive" {
+            return Some((Self::Dead, false));
+        } else {
+            Self::Owned(COLOR_NAMES.iter().position(|&c| c == token)?)
+        };
+        Some((condition, true))
+    }
+
+    fn matches(&self, state: &MusicState) -> bool {
+        match self {
+            Self::Owned(i) => state.masks_owned[*i],
+            Self::Active(i) => state.active_color[*i],
+            Self::DangerAbove(value) => state.danger > *value,
+            Self::DangerBelow(value) => state.danger < *value,
+            Self::Dead => state.dead,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LayerRule {
+    pub track: Option<usize>, // None applies to all tracks
+    volume: f64,
+    fade: f64, // seconds to go from silent to full volume
+    sync: Quantize,
+    conditions: Vec<(Condition, bool)>,
+    hold: f64,               // bars the conditions have to hold before the rule applies
+    held_since: Option<f64>, // time the conditions started to hold
+    applies: bool,
+}
+
+impl LayerRule {
+    // layer <channel|*> <volume> [fade=<seconds>] [sync=<now|beat|bar>] [when <conditions>...] [for=<bars>]
+    pub(crate) fn parse(args: &[&str]) -> Result<(String, Self), String> {
+        if args.len() < 2 {
+            return Err("expects 2 arguments".to_owned());
+        }
+        let (channel, volume, rest) = (args[0], args[1], &args[2..]);
+
+        let mut rule = Self {
+            track: None,
+            volume: volume
+                .parse()
+                .map_err(|_| format!("invalid volume \"{}\"", volume))?,
+            fade: 1.0,
+            sync: Quantize::Immediate,
+            conditions: Vec::new(),
+            hold: 0.0,
+            held_since: None,
+            applies: false,
+        };
+
+        let mut in_conditions = false;
+        for &token in rest {
+            if let Some(value) = token.strip_prefix("fade=") {
+                rule.fade = value
+                    .parse()
+                    .map_err(|_| format!("invalid fade \"{}\"", value))?;
+            } else if let Some(value) = token.strip_prefix("sync=") {
+                rule.sync = Quantize::from_name(value)
+                    .ok_or_else(|| format!("invalid sync \"{}\"", value))?;
+            } else if let Some(value) = token.strip_prefix("for=") {
+                rule.hold = value
+                    .parse()
+                    .map_err(|_| format!("invalid bar count \"{}\"", value))?;
+            } else if token == "when" {
+                in_conditions = true;
+            } else if in_conditions {
+                rule.conditions.push(
+                    Condition::parse(token)
+                        .ok_or_else(|| format!("invalid condition \"{}\"", token))?,
+                );
+            } else {
+                return Err(format!("unexpected \"{}\"", token));
+            }
+        }
+
+        Ok((channel.to_owned(), rule))
+    }
+
+    pub fn reset(&mut self) {
+        self.held_since = None;
+        self.applies = false;
+    }
+
+    fn update(&mut self, state: &MusicState, time: f64, seconds_per_bar: f64) {
+        let holds = self
+            .conditions
+            .iter()
+            .all(|(condition, expected)| condition.matches(state) == *expected);
+        if holds {
+            let held_since = *self.held_since.get_or_insert(time);
+            self.applies = time - held_since >= self.hold * seconds_per_bar;
+        } else {
+            self.held_since = None;
+            self.applies = false;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PendingTarget {
+    target: f64,
+    fade: f64,
+    beat: f64, // song beat at which the fade starts
+}
+
+// Volume of a single track, moves towards the target at the speed of the fade
+#[derive(Debug, Clone, Copy)]
+pub struct Layer {
+    pub volume: f64,
+    target: f64,
+    fade: f64,
+    sync: Quantize,
+    pending: Option<PendingTarget>,
+}
+
+impl Default for Layer {
+    fn default() -> Self {
+        Self {
+            volume: 0.0,
+            target: 0.0,
+            fade: 1.0,
+            sync: Quantize::Immediate,
+            pending: None,
+        }
+    }
+}
+
+impl Layer {
+    // Ignores pending changes, a silent song has no beat to wait for
+    pub fn is_silent(&self) -> bool {
+        self.volume == 0.0 && self.target == 0.0
+    }
+
+    fn set_target(&mut self, target: f64, fade: f64, beat: f64, bar_length: usize) {
+        let next_target = self.pending.map_or(self.target, |p| p.target);
+        if next_target == target {
+            return;
+        }
+
+        self.pending = Some(PendingTarget {
+            target,
+            fade,
+            beat: self.sync.next_boundary(beat, bar_length),
+        });
+    }
+
+    pub fn start_pending(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            self.target = pending.target;
+            self.fade = pending.fade;
+        }
+    }
+
+    // Jumps straight to the target, used when switching songs
+    pub fn snap(&mut self) {
+        self.start_pending();
+        self.volume = self.target;
+    }
+
+    pub fn advance(&mut self, dt: f64, beat: f64) {
+        if self.pending.is_some_and(|p| beat >= p.beat) {
+            self.start_pending();
+        }
+
+        let step = if self.fade > 0.0 { dt / self.fade } else { 1.0 };
+        self.volume = if self.volume < self.target {
+            (self.volume + step).min(self.target)
+        } else {
+            (self.volume - step).max(self.target)
+        };
+    }
+}
+
+// The last matching rule of each track wins, tracks without a matching rule fade out
+pub fn update_layers(
+    rules: &mut [LayerRule],
+    layers: &mut [Layer],
+    state: &MusicState,
+    time: f64,
+    beat: f64,
+    tempo: f64,
+    bar_length: usize,
+) {
+    let seconds_per_bar = bar_length as f64 / tempo;
+    for rule in rules.iter_mut() {
+        rule.update(state, time, seconds_per_bar);
+    }
+
+    for (i, layer) in layers.iter_mut().enumerate() {
+        let rule = rules
+            .iter()
+            .rev()
+            .find(|rule| rule.applies && rule.track.is_none_or(|track| track == i));
+        // Fading out keeps the sync of the rule that faded in
+        if let Some(rule) = rule {
+            layer.sync = rule.sync;
+            layer.set_target(rule.volume, rule.fade, beat, bar_length);
+        } else {
+            layer.set_target(0.0, layer.fade, beat, bar_length);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use crate::audio::sound::{FilterState, SoundEffect, SoundId};
+
+pub const MAX_VOICES: usize = 32;
+
+pub enum SfxEvent {
+    Play {
+        sound: SoundId,
+        volume: f32,
+        pan: f32,
+    },
+    Stop(SoundId),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Voice {
+    pub sound: SoundId,
+    pub start: f64,  // start time
+    pub volume: f64, // multiplied with the volume of the sound itself
+    pub pan: f64,    // -1.0 is fully left, 1.0 is fully right
+    pub filter_state: FilterState,
+}
+
+pub struct Mixer {
+    voices: [Option<Voice>; MAX_VOICES],
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mixer {
+    pub fn new() -> Self {
+        Self {
+            voices: [None; MAX_VOICES],
+        }
+    }
+
+    pub fn play(&mut self, voice: Voice) {
+        // Take a free slot, or steal the voice that has been playing the longest
+        let slot = if let Some(free) = self.voices.iter().position(|v| v.is_none()) {
+            free
+        } else {
+            self.voices
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| {
+                    let a = a.map_or(f64::MIN, |v| v.start);
+                    let b = b.map_or(f64::MIN, |v| v.start);
+                    a.total_cmp(&b)
+                })
+                .map(|(i, _)| i)
+                .unwrap_or(0)
+        };
+        self.voices[slot] = Some(voice);
+    }
+
+    // Stops every voice that is playing this sound
+    pub fn stop(&mut self, sound: SoundId) {
+        for voice in self.voices.iter_mut() {
+            if voice.is_some_and(|v| v.sound == sound) {
+                *voice = None;
+            }
+        }
+    }
+
+    // Returns the (left, right) signal of all voices at time t
+    pub fn mix(&mut self, t: f64, sounds: &[SoundEffect], sample_duration: f64) -> (f64, f64) {
+        let mut left = 0.0;
+        let mut right = 0.0;
+
+        for slot in self.voices.iter_mut() {
+            let Some(voice) = slot else {
+                continue;
+            };
+            let Some(sound) = sounds.get(voice.sound.0 as usize) else {
+                *slot = None;
+                continue;
+            };
+            if t < voice.start {
+                continue;
+            }
+            if sound.is_finished(t - voice.start) {
+                *slot = None;
+                continue;
+            }
+
+            let (l, r) = sound.play(t, voice.start, sample_duration, &mut voice.filter_state);
+            left += voice.volume * l * (1.0 - voice.pan).min(1.0);
+            right += voice.volume * r * (1.0 + voice.pan).min(1.0);
+        }
+
+        (left, right)
+    }
+}
+
+// Peaks above the knee get compressed smoothly towards 1.0 instead of clipping
+const LIMITER_KNEE: f32 = 0.7;
+// Seconds for the gain to recover after a peak
+const LIMITER_RELEASE: f32 = 0.2;
+
+pub struct Limiter {
+    gain: f32,
+}
+
+impl Default for Limiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Limiter {
+    pub fn new() -> Self {
+        Self { gain: 1.0 }
+    }
+
+    pub fn process(&mut self, left: f32, right: f32, sample_duration: f32) -> (f32, f32) {
+        let peak = left.abs().max(right.abs());
+        let target = if peak > LIMITER_KNEE {
+            let range = 1.0 - LIMITER_KNEE;
+            let limited = LIMITER_KNEE + range * ((peak - LIMITER_KNEE) / range).tanh();
+            limited / peak
+        } else {
+            1.0
+        };
+
+        // Duck immediately so peaks never get through, recover slowly to avoid pumping
+        if target < self.gain {
+            self.gain = target;
+        } else {
+            self.gain = (self.gain + sample_duration / LIMITER_RELEASE).min(target);
+        }
+
+        (left * self.gain, right * self.gain)
+    }
+}
@@ -1,282 +1,390 @@
 use glam::*;
-use std::sync::mpsc::{Sender, channel};
-
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub mod clock;
+pub mod engine;
+pub mod layers;
+pub mod mixer;
 pub mod notes;
+pub mod output;
+pub mod ring;
+pub mod song;
 pub mod sound;
-use sound::{play_music, play_sfx};
-
-use crate::audio::sound::SoundTypes;
-use crate::audio::sound::sawtooth_wave;
-use crate::game::Key;
-
-#[derive(Clone)]
-struct AudioSettings {
-    volume: f32,
+pub mod synth;
+pub mod wav;
+use clock::{BeatClock, BeatPosition, MusicEvent, Quantize};
+use engine::AudioEngine;
+use layers::MusicState;
+use mixer::SfxEvent;
+use output::{DeviceOutput, NullSink, Output};
+use ring::{Consumer, Producer, ring};
+use sound::{Music, SongId, SoundId};
+use synth::SynthEvent;
+use wav::Sample;
+
+use crate::config::{CONFIG_PATH, Config};
+
+// Positional sound effects fade out towards this distance from the listener, in world units
+const HEARING_DISTANCE: f32 = 320.0;
+// Horizontal distance at which a sound is heard only on one side
+const PAN_DISTANCE: f32 = 160.0;
+
+// Messages sent within one frame, the music state alone is sent every frame
+const COMMAND_CAPACITY: usize = 256;
+
+const EXPORT_SAMPLE_RATE: u32 = 48000;
+
+// How often to look for an audio device again while playing without one
+const REOPEN_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct AudioSettings {
+    pub(crate) master_volume: f32,
+    pub(crate) music_volume: f32,
+    pub(crate) sfx_volume: f32,
 }
 impl Default for AudioSettings {
     fn default() -> Self {
-        Self { volume: 0.5 }
+        Self {
+            master_volume: 0.5,
+            music_volume: 1.0,
+            sfx_volume: 1.0,
+        }
+    }
+}
+
+impl AudioSettings {
+    pub(crate) fn from_config(config: &Config) -> Self {
+        let default = Self::default();
+        let volume = |key, default: f32| config.get(key).unwrap_or(default).clamp(0.0, 1.0);
+        Self {
+            master_volume: volume("master_volume", default.master_volume),
+            music_volume: volume("music_volume", default.music_volume),
+            sfx_volume: volume("sfx_volume", default.sfx_volume),
+        }
+    }
+
+    pub(crate) fn write_to_config(&self, config: &mut Config) {
+        config.set("master_volume", self.master_volume);
+        config.set("music_volume", self.music_volume);
+        config.set("sfx_volume", self.sfx_volume);
+    }
+}
+
+// Everything the game sends to the audio thread, nothing in here may allocate
+pub(crate) enum AudioCommand {
+    Synth(SynthEvent),
+    Sfx(SfxEvent),
+    MusicState(MusicState),
+    Music(MusicEvent),
+    Settings(AudioSettings),
+}
+
+// Counted on the audio thread instead of printed, reported by `Audio::tick`
+#[derive(Default)]
+pub(crate) struct AudioDiagnostics {
+    pub(crate) timing_errors: AtomicU32,
+    pub(crate) dropped_commands: AtomicU32,
+}
+
+// The engine together with the receiving end of the audio commands
+pub(crate) struct EngineState {
+    engine: AudioEngine,
+    commands: Consumer<AudioCommand>,
+}
+
+impl EngineState {
+    fn render(&mut self, data: &mut [f32], channels: usize) {
+        while let Some(command) = self.commands.pop() {
+            match command {
+                AudioCommand::Synth(synth_event) => self.engine.handle_synth(synth_event),
+                AudioCommand::Sfx(sfx_event) => self.engine.handle_sfx(sfx_event),
+                AudioCommand::MusicState(music_state) => self.engine.music_state = music_state,
+                AudioCommand::Music(music_event) => self.engine.handle_music_event(music_event),
+                AudioCommand::Settings(settings) => self.engine.settings = settings,
+            }
+        }
+
+        self.engine.render(data, channels);
     }
 }
 
 pub(crate) struct Audio {
-    _host: cpal::Host,     //raii
-    _device: cpal::Device, //raii
-    _stream: cpal::Stream, //raii
+    output: Output,
+    state: Arc<Mutex<EngineState>>,
+
+    commands: Producer<AudioCommand>,
+    clock: Arc<BeatClock>,
+    diagnostics: Arc<AudioDiagnostics>,
+    reported_diagnostics: (u32, u32), // timing errors, dropped commands
 
-    pub key_sender: Sender<(Key, bool)>,
-    pub sfx_sender: Sender<(SoundTypes, bool)>,
-    pub color_mask_sender: Sender<UVec3>,
+    settings: AudioSettings,
+
+    sound_names: Vec<&'static str>,
+    song_names: Vec<String>,
+
+    listener: Vec2, // world position positional sounds are heard from
 }
 
 impl Audio {
-    pub(crate) fn new() -> Self {
-        use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-        let host = cpal::default_host();
-        let device = host
-            .default_output_device()
-            .expect("No audio output device available.");
-        let mut supported_configs_range = device.supported_output_configs().unwrap();
-
-        let supported_config = supported_configs_range
-            .find(|config| {
-                config.sample_format() == cpal::SampleFormat::F32 && config.channels() == 2
-            })
-            .expect("Failed to find a suitable audio format");
-        let supported_config = if let Some(cfg) = supported_config.try_with_sample_rate(48000) {
-            cfg
-        } else if let Some(cfg) = supported_config.try_with_sample_rate(441000) {
-            cfg
-        } else if let Some(cfg) = supported_config.try_with_sample_rate(22050) {
-            cfg
+    // Loads every song in assets/music and starts with the one called `song`
+    pub(crate) fn new(song: &str) -> Self {
+        let settings = AudioSettings::from_config(&Config::from_file(CONFIG_PATH));
+        let (commands, command_consumer) = ring(COMMAND_CAPACITY);
+
+        let mut song_paths = std::fs::read_dir("assets/music")
+            .expect("Failed to read assets/music")
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|e| e == "txt"))
+            .collect::<Vec<_>>();
+        song_paths.sort();
+
+        let songs = song_paths
+            .iter()
+            .map(|path| Music::load(path.to_str().unwrap()))
+            .collect();
+        let song_names = song_paths
+            .iter()
+            .map(|path| path.file_stem().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+
+        let mut engine = AudioEngine::new(48000, settings, songs);
+        let sound_names = engine.soundeffects.names.clone();
+        let clock = engine.clock.clone();
+        let diagnostics = engine.diagnostics.clone();
+
+        if let Some(i) = song_names.iter().position(|name| name == song) {
+            engine.handle_music_event(MusicEvent::SwitchSong {
+                song: SongId(i as u32),
+                quantize: Quantize::Immediate,
+            });
         } else {
-            supported_config.with_max_sample_rate()
-        };
+            println!("Unknown song: {}", song);
+        }
+
+        let state = Arc::new(Mutex::new(EngineState {
+            engine,
+            commands: command_consumer,
+        }));
 
-        let sample_format = supported_config.sample_format();
-        let sample_rate = supported_config.sample_rate();
-        let channels = supported_config.channels();
+        let output = Self::open_output(&state);
 
-        let mut config: cpal::StreamConfig = supported_config.clone().into();
-        config.buffer_size = cpal::BufferSize::Fixed(512); // ~11.6ms latency at 44.1kHz
+        Self {
+            output,
+            state,
 
-        assert!(sample_format == cpal::SampleFormat::F32);
+            commands,
+            clock,
+            diagnostics,
+            reported_diagnostics: (0, 0),
 
-        let settings: AudioSettings = AudioSettings::default();
-        let (key_sender, key_recv) = channel();
-        let (sfx_sender, sfx_recv) = channel();
-        let (color_mask_sender, color_mask_recv) = channel();
+            settings,
 
-        struct StreamContext {
-            settings: AudioSettings,
+            sound_names,
+            song_names,
+
+            listener: Vec2::ZERO,
         }
+    }
 
-        let context = StreamContext {
-            settings: settings.clone(),
-        };
+    fn open_output(state: &Arc<Mutex<EngineState>>) -> Output {
+        match DeviceOutput::open(state) {
+            Ok(device) => Output::Device(device),
+            Err(err) => {
+                println!(
+                    "Failed to open audio device ({}), continuing without sound.",
+                    err
+                );
+                Output::Null {
+                    _sink: NullSink::new(state),
+                    retry_time: Instant::now() + REOPEN_INTERVAL,
+                }
+            }
+        }
+    }
 
-        let mut last_time = 0.0;
-        let mut time = 0.0;
-        let mut piano_notes: [bool; 17] = [false; 17];
-        let mut max_value: f32 = 0.0;
-
-        let mut music = sound::Music::new();
-        let mut t0_music = DVec4::ZERO; // red, green, blue, death
-        let mut color_mask_music = UVec3::ZERO;
-
-        let soundeffects = sound::SoundEffects::new();
-        let mut start_footstep_sound: bool = false;
-        let mut stop_footstep_sound: bool = false;
-        let mut start_jump_sound: bool = false;
-        let mut start_pickup_sound: bool = false;
-        let mut start_death_sound: bool = false;
-        let mut play_footstep_sound: bool = false;
-        let mut play_jump_sound: bool = false;
-        let mut play_pickup_sound: bool = false;
-        let mut play_death_sound: bool = false;
-        let mut t0_footstep_sound: f64 = 0.0;
-        let mut t0_jump_sound: f64 = 0.0;
-        let mut t0_pickup_sound: f64 = 0.0;
-        let mut t0_death_sound: f64 = 0.0;
-
-        let stream = device
-            .build_output_stream(
-                &config,
-                move |data: &mut [f32], _info: &cpal::OutputCallbackInfo| {
-                    while let Ok((key, down)) = key_recv.try_recv() {
-                        match key {
-                            Key::MusicC3 => piano_notes[0] = down,
-                            Key::MusicCs3 => piano_notes[1] = down,
-                            Key::MusicD3 => piano_notes[2] = down,
-                            Key::MusicDs3 => piano_notes[3] = down,
-                            Key::MusicE3 => piano_notes[4] = down,
-                            Key::MusicF3 => piano_notes[5] = down,
-                            Key::MusicFs3 => piano_notes[6] = down,
-                            Key::MusicG3 => piano_notes[7] = down,
-                            Key::MusicGs3 => piano_notes[8] = down,
-                            Key::MusicA3 => piano_notes[9] = down,
-                            Key::MusicAs3 => piano_notes[10] = down,
-                            Key::MusicB3 => piano_notes[11] = down,
-                            Key::MusicC4 => piano_notes[12] = down,
-                            Key::MusicCs4 => piano_notes[13] = down,
-                            Key::MusicD4 => piano_notes[14] = down,
-                            Key::MusicDs4 => piano_notes[15] = down,
-                            Key::MusicE4 => piano_notes[16] = down,
-                            _ => {}
-                        }
+    // Call every frame, reopens the output when the device got lost and keeps looking for a
+    // device while there is none
+    pub(crate) fn tick(&mut self) {
+        match &mut self.output {
+            Output::Device(device) if device.device_lost.load(Ordering::Relaxed) => {
+                println!("Audio device lost, reopening audio output.");
+                // Drop the old stream before opening a new one
+                self.output = Output::Closed;
+                self.output = Self::open_output(&self.state);
+            }
+            Output::Null { retry_time, .. } if Instant::now() >= *retry_time => {
+                match DeviceOutput::open(&self.state) {
+                    Ok(device) => {
+                        println!("Found an audio device, sound is back.");
+                        self.output = Output::Device(device);
                     }
+                    Err(_) => *retry_time = Instant::now() + REOPEN_INTERVAL,
+                }
+            }
+            Output::Closed => self.output = Self::open_output(&self.state),
+            _ => {}
+        }
 
-                    while let Ok((sfx_event, play)) = sfx_recv.try_recv() {
-                        match sfx_event {
-                            SoundTypes::FootstepSound => {
-                                if play {
-                                    start_footstep_sound = true;
-                                } else {
-                                    stop_footstep_sound = true;
-                                }
-                            }
-                            SoundTypes::JumpSound => {
-                                start_jump_sound = play;
-                            }
-                            SoundTypes::PickupSound => {
-                                start_pickup_sound = play;
-                            }
-                            SoundTypes::DeathSound => {
-                                start_death_sound = play;
-                            }
-                        }
-                    }
+        let timing_errors = self.diagnostics.timing_errors.load(Ordering::Relaxed);
+        let dropped_commands = self.diagnostics.dropped_commands.load(Ordering::Relaxed);
+        if (timing_errors, dropped_commands) != self.reported_diagnostics {
+            println!(
+                "Audio glitches: {} timing errors, {} dropped commands",
+                timing_errors, dropped_commands
+            );
+            self.reported_diagnostics = (timing_errors, dropped_commands);
+        }
+    }
 
-                    while let Ok(color_mask) = color_mask_recv.try_recv() {
-                        color_mask_music = color_mask;
+    // Never blocks, when the audio thread falls behind the command gets dropped and counted
+    fn send(&self, command: AudioCommand) {
+        if self.commands.push(command).is_err() {
+            self.diagnostics
+                .dropped_commands
+                .fetch_add(1, Ordering::Relaxed);
+        }
+    }
 
-                        // first time each mask is picked up
-                        for i in 0..3 {
-                            if t0_music[i] == 0.0 && color_mask_music[i] > 0 {
-                                t0_music[i] = time + 1.;
-                            }
-                        }
-                    }
+    pub(crate) fn send_synth(&self, synth_event: SynthEvent) {
+        self.send(AudioCommand::Synth(synth_event));
+    }
 
-                    let sample_duration = 1.0 / sample_rate as f64;
-                    let chunk_time = (data.len() / channels as usize) as f64 / sample_rate as f64;
-
-                    let settings = &context.settings;
-                    //write stream data here
-                    let mut t = time;
-
-                    for frame in data.chunks_exact_mut(channels as usize) {
-                        if ((t - last_time) - sample_duration).abs() > 0.0000000001 {
-                            println!("Timing error! t: {}, last_time: {}, sample_duration: {}, diff: {}, err: {}",
-                            t,
-                            last_time, sample_duration, t - last_time, ((t - last_time) - sample_duration).abs());
-                        }
-                        last_time = t;
-
-                        let mut value = play_music(t, &t0_music, &color_mask_music, &mut music);
-
-                        if start_footstep_sound {
-                            start_footstep_sound = false;
-                            play_footstep_sound = true;
-                            t0_footstep_sound = t;
-                        }
-
-                        if stop_footstep_sound {
-                            stop_footstep_sound = false;
-                            play_footstep_sound = false;
-                        }
-
-                        if start_jump_sound {
-                            start_jump_sound = false;
-                            play_jump_sound = true;
-                            t0_jump_sound = t;
-                        }
-
-                        if start_pickup_sound {
-                            start_pickup_sound = false;
-                            play_pickup_sound = true;
-                            t0_pickup_sound = t;
-                        }
-
-                        if start_death_sound {
-                            start_death_sound = false;
-                            play_death_sound = true;
-                            t0_death_sound = t;
-                            t0_music[3] = t;
-                        }
-
-                        if play_footstep_sound {
-                            value += play_sfx(t, t0_footstep_sound, &soundeffects.footstep)
-                        }
-
-                        if play_jump_sound {
-                            value += play_sfx(t, t0_jump_sound, &soundeffects.jump)
-                        }
-
-                        if play_pickup_sound {
-                            value += play_sfx(t, t0_pickup_sound, &soundeffects.pickup)
-                        }
-
-                        if play_death_sound {
-                            value += play_sfx(t, t0_death_sound, &soundeffects.death)
-                        }
-
-                        for (i, note_played ) in piano_notes.iter().enumerate(){
-                            if *note_played {
-                               value += 0.5 * sawtooth_wave(t, 440. * 1.05946309436_f64.powi(i as i32 - 9));
-                            }
-                        }
-
-                        // normalize output
-                        let value = value as f32;
-                        let value = value * settings.volume;
-
-                        if value.abs() > max_value {
-                            max_value = value.abs();
-                            if max_value > 1.0 {
-                                println!("WARNING: audio amplitude greater than 1");
-                                println!("\tnormalizing amplitude from now on");
-                            }
-                        }
-
-                        let value = if max_value > 1.0 {
-                            value / max_value
-                        } else {
-                            value
-                        };
-
-                        // left and right channel
-                        frame[0] = value; // * (1.0 - settings.panning).min(0.5) * 2.0;
-                        frame[1] = value; // * (settings.panning).min(0.5) * 2.0;
-
-                        // Output same audio for all other channels for now
-                        for sample in frame[2..].iter_mut() {
-                            *sample = value;
-                        }
-
-                        t += sample_duration;
-                    }
+    // Call every frame, see the layer rules in assets/music
+    pub(crate) fn set_music_state(&self, music_state: MusicState) {
+        self.send(AudioCommand::MusicState(music_state));
+    }
 
-                    time += chunk_time;
-                    assert!((t - time).abs() < 0.00001);
-                },
-                move |err| {
-                    //deal with errors I guess
-                    println!("Audio system error: {:?}", err);
-                },
-                None,
-            )
-            .unwrap();
-        stream.play().unwrap();
+    // Renders the full soundtrack with all masks collected, used for trailers
+    pub(crate) fn export_music(music_path: &str, path: &str, seconds: f64) {
+        let sample = Self::render_music(Music::load(music_path), seconds);
+        sample.store_to_file(path);
+        println!("Exported {} seconds of music to {}", seconds, path);
+    }
 
-        Self {
-            _host: host,
-            _device: device,
-            _stream: stream,
+    fn render_music(music: Music, seconds: f64) -> Sample {
+        let mut engine =
+            AudioEngine::new(EXPORT_SAMPLE_RATE, AudioSettings::default(), vec![music]);
+        engine.music_state = MusicState {
+            masks_owned: [true; 3],
+            ..Default::default()
+        };
+        engine.render_offline(seconds)
+    }
+
+    pub(crate) fn sound_id(&self, name: &str) -> Option<SoundId> {
+        self.sound_names
+            .iter()
+            .position(|&n| n == name)
+            .map(|i| SoundId(i as u32))
+    }
+
+    pub(crate) fn play_sfx(&self, name: &str) {
+        self.play_sfx_panned(name, 1.0, 0.0);
+    }
 
-            key_sender,
-            sfx_sender,
-            color_mask_sender,
+    pub(crate) fn play_sfx_panned(&self, name: &str, volume: f32, pan: f32) {
+        if let Some(sound) = self.sound_id(name) {
+            self.send(AudioCommand::Sfx(SfxEvent::Play { sound, volume, pan }));
+        } else {
+            println!("Unknown sound effect: {}", name);
+        }
+    }
+
+    // Call every frame with the center of the screen in world space
+    pub(crate) fn set_listener(&mut self, position: Vec2) {
+        self.listener = position;
+    }
+
+    // Pans the sound to the side it comes from and attenuates it with distance
+    pub(crate) fn play_sfx_at(&self, name: &str, position: Vec2) {
+        let offset = position - self.listener;
+        let volume = (1.0 - offset.length() / HEARING_DISTANCE).max(0.0).powi(2);
+        let pan = (offset.x / PAN_DISTANCE).clamp(-1.0, 1.0);
+
+        if volume > 0.01 {
+            self.play_sfx_panned(name, volume, pan);
         }
     }
+
+    pub(crate) fn settings(&self) -> AudioSettings {
+        self.settings
+    }
+
+    // Applies the settings right away and stores them in the config file
+    pub(crate) fn set_settings(&mut self, settings: AudioSettings) {
+        self.settings = settings;
+        self.send(AudioCommand::Settings(settings));
+
+        let mut config = Config::from_file(CONFIG_PATH);
+        settings.write_to_config(&mut config);
+        config.store_to_file(CONFIG_PATH);
+    }
+
+    // Position of the current song, for visuals that move to the music
+    pub(crate) fn beat_position(&self) -> BeatPosition {
+        self.clock.position()
+    }
+
+    pub(crate) fn switch_song(&self, name: &str, quantize: Quantize) {
+        if let Some(i) = self.song_names.iter().position(|n| n == name) {
+            self.send(AudioCommand::Music(MusicEvent::SwitchSong {
+                song: SongId(i as u32),
+                quantize,
+            }));
+        } else {
+            println!("Unknown song: {}", name);
+        }
+    }
+
+    // Plays a sound effect in time with the music
+    pub(crate) fn play_stinger(&self, name: &str, quantize: Quantize) {
+        if let Some(sound) = self.sound_id(name) {
+            self.send(AudioCommand::Music(MusicEvent::Stinger {
+                sound,
+                volume: 1.0,
+                quantize,
+            }));
+        } else {
+            println!("Unknown sound effect: {}", name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(seconds: f64) -> Sample {
+        Audio::render_music(Music::load("assets/music/level0.txt"), seconds)
+    }
+
+    #[test]
+    fn exported_music_is_audible_and_deterministic() {
+        let sample = render(2.0);
+        assert_eq!(sample.frames.len(), 2 * EXPORT_SAMPLE_RATE as usize);
+        assert!(sample.frames.iter().flatten().all(|s| s.is_finite()));
+        assert!(sample.frames.iter().flatten().any(|s| s.abs() > 0.01));
+        assert_eq!(sample.frames, render(2.0).frames);
+    }
+
+    #[test]
+    fn exported_music_encodes_as_wave() {
+        let sample = render(0.5);
+        let bytes = sample.encode();
+        let data_size = sample.frames.len() * 4;
+        assert_eq!(bytes.len(), 44 + data_size);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(&bytes[4..8], &(36 + data_size as u32).to_le_bytes());
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(&bytes[24..28], &EXPORT_SAMPLE_RATE.to_le_bytes());
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(&bytes[40..44], &(data_size as u32).to_le_bytes());
+
+        let decoded = Sample::parse(&bytes).unwrap();
+        assert_eq!(decoded.sample_rate, EXPORT_SAMPLE_RATE);
+        assert_eq!(decoded.frames.len(), sample.frames.len());
+    }
 }
@@ -18,6 +18,40 @@ const fn note_from_a4(offset: i32) -> f64 {
 
 pub const REST: f64 = 0.0;
 
+const NOTE_NAMES: [&str; 12] = [
+    "C", "CS", "D", "DS", "E", "F", "FS", "G", "GS", "A", "AS", "B",
+];
+
+// Parses note names like "A4", "CS3" or "C#3", "--" is a rest
+pub fn note_from_name(name: &str) -> Option<f64> {
+    if name == "--" {
+        return Some(REST);
+    }
+
+    let split = name.find(|c: char| c.is_ascii_digit())?;
+    let (note, octave) = name.split_at(split);
+    let note = note.to_ascii_uppercase().replace('#', "S");
+    let index = NOTE_NAMES.iter().position(|&n| n == note)? as i32;
+    let octave: i32 = octave.parse().ok()?;
+
+    let offset = (octave - 4) * 12 + index - 9;
+    Some(A4_REF * SEMITONE.powi(offset))
+}
+
+// MIDI note numbers, 69 is A4
+pub fn note_from_midi(midi: i32) -> f64 {
+    A4_REF * SEMITONE.powi(midi - 69)
+}
+
+// Name in the music file format, like "CS4"
+pub fn midi_note_name(midi: i32) -> String {
+    format!(
+        "{}{}",
+        NOTE_NAMES[midi.rem_euclid(12) as usize],
+        midi.div_euclid(12) - 1
+    )
+}
+
 // Octave 0
 pub const A0: f64 = note_from_a4(-48);
 pub const AS0: f64 = note_from_a4(-47);
@@ -0,0 +1,212 @@
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SizedSample};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use super::EngineState;
+
+// Frames rendered at a time, ~11.6ms latency at 44.1kHz
+const BUFFER_FRAMES: usize = 512;
+
+#[derive(Debug)]
+pub(crate) enum AudioError {
+    NoOutputDevice,
+    NoSupportedConfig,
+    UnsupportedSampleFormat(cpal::SampleFormat),
+    SupportedConfigs(cpal::SupportedStreamConfigsError),
+    BuildStream(cpal::BuildStreamError),
+    PlayStream(cpal::PlayStreamError),
+}
+
+impl std::fmt::Display for AudioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoOutputDevice => write!(f, "no audio output device available"),
+            Self::NoSupportedConfig => write!(f, "no supported output configuration"),
+            Self::UnsupportedSampleFormat(format) => {
+                write!(f, "unsupported sample format {}", format)
+            }
+            Self::SupportedConfigs(err) => err.fmt(f),
+            Self::BuildStream(err) => err.fmt(f),
+            Self::PlayStream(err) => err.fmt(f),
+        }
+    }
+}
+
+pub(crate) enum Output {
+    Device(DeviceOutput),
+    Null {
+        _sink: NullSink,     //raii
+        retry_time: Instant, // when to try the device again
+    },
+    Closed, // between dropping a lost stream and opening the next one
+}
+
+pub(crate) struct DeviceOutput {
+    _host: cpal::Host,     //raii
+    _device: cpal::Device, //raii
+    _stream: cpal::Stream, //raii
+
+    pub(crate) device_lost: Arc<AtomicBool>,
+}
+
+impl DeviceOutput {
+    pub(crate) fn open(state: &Arc<Mutex<EngineState>>) -> Result<Self, AudioError> {
+        let host = cpal::default_host();
+        let device = host
+            .default_output_device()
+            .ok_or(AudioError::NoOutputDevice)?;
+
+        // Prefer stereo f32, but take whatever the device offers and convert
+        let supported_config = device
+            .supported_output_configs()
+            .map_err(AudioError::SupportedConfigs)?
+            .max_by_key(|config| {
+                (
+                    config.channels() == 2,
+                    config.sample_format() == cpal::SampleFormat::F32,
+                    config.sample_format().is_float(),
+                    config.sample_format().sample_size(),
+                )
+            })
+            .ok_or(AudioError::NoSupportedConfig)?;
+
+        // Keep the sample rate the engine already runs at if possible
+        let engine_sample_rate = state.lock().unwrap().engine.sample_rate;
+        let supported_config = [engine_sample_rate, 48000, 44100, 22050]
+            .into_iter()
+            .find_map(|rate| supported_config.try_with_sample_rate(rate))
+            .unwrap_or_else(|| supported_config.with_max_sample_rate());
+
+        let sample_format = supported_config.sample_format();
+        let sample_rate = supported_config.sample_rate();
+
+        let mut config: cpal::StreamConfig = supported_config.into();
+        config.buffer_size = cpal::BufferSize::Fixed(BUFFER_FRAMES as u32);
+
+        if sample_rate != engine_sample_rate {
+            state.lock().unwrap().engine.set_sample_rate(sample_rate);
+        }
+
+        let device_lost = Arc::new(AtomicBool::new(false));
+
+        let stream = match sample_format {
+            cpal::SampleFormat::F32 => build_stream::<f32>(&device, &config, state, &device_lost),
+            cpal::SampleFormat::F64 => build_stream::<f64>(&device, &config, state, &device_lost),
+            cpal::SampleFormat::I8 => build_stream::<i8>(&device, &config, state, &device_lost),
+            cpal::SampleFormat::I16 => build_stream::<i16>(&device, &config, state, &device_lost),
+            cpal::SampleFormat::I32 => build_stream::<i32>(&device, &config, state, &device_lost),
+            cpal::SampleFormat::U8 => build_stream::<u8>(&device, &config, state, &device_lost),
+            cpal::SampleFormat::U16 => build_stream::<u16>(&device, &config, state, &device_lost),
+            cpal::SampleFormat::U32 => build_stream::<u32>(&device, &config, state, &device_lost),
+            format => return Err(AudioError::UnsupportedSampleFormat(format)),
+        }?;
+        stream.play().map_err(AudioError::PlayStream)?;
+
+        Ok(Self {
+            _host: host,
+            _device: device,
+            _stream: stream,
+            device_lost,
+        })
+    }
+}
+
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    state: &Arc<Mutex<EngineState>>,
+    device_lost: &Arc<AtomicBool>,
+) -> Result<cpal::Stream, AudioError>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels as usize;
+    let state = state.clone();
+    let device_lost = device_lost.clone();
+
+    // The engine renders f32, other formats get converted from this buffer. It is allocated
+    // here because the audio thread must not allocate, devices asking for more than the
+    // requested buffer size get it in several pieces
+    let mut buffer = vec![0.0f32; BUFFER_FRAMES * channels];
+
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _info: &cpal::OutputCallbackInfo| {
+                for data in data.chunks_mut(buffer.len()) {
+                    let buffer = &mut buffer[..data.len()];
+
+                    // Never wait on the game thread, output silence instead
+                    if let Ok(mut state) = state.try_lock() {
+                        state.render(buffer, channels);
+                    } else {
+                        buffer.fill(0.0);
+                    }
+
+                    for (sample, value) in data.iter_mut().zip(buffer.iter()) {
+                        *sample = T::from_sample(*value);
+                    }
+                }
+            },
+            move |err| {
+                println!("Audio system error: {}", err);
+                if matches!(
+                    err,
+                    cpal::StreamError::DeviceNotAvailable | cpal::StreamError::StreamInvalidated
+                ) {
+                    device_lost.store(true, Ordering::Relaxed);
+                }
+            },
+            None,
+        )
+        .map_err(AudioError::BuildStream)
+}
+
+// Keeps the engine running in real time without a device, so messages are still consumed
+pub(crate) struct NullSink {
+    running: Arc<AtomicBool>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl NullSink {
+    pub(crate) fn new(state: &Arc<Mutex<EngineState>>) -> Self {
+        let running = Arc::new(AtomicBool::new(true));
+
+        let thread = {
+            let state = state.clone();
+            let running = running.clone();
+            std::thread::spawn(move || {
+                let mut buffer = vec![0.0; BUFFER_FRAMES * 2];
+                let mut rendered = 0.0;
+                let start = std::time::Instant::now();
+
+                while running.load(Ordering::Relaxed) {
+                    std::thread::sleep(std::time::Duration::from_millis(5));
+
+                    let mut state = state.lock().unwrap();
+                    let chunk_time = BUFFER_FRAMES as f64 / state.engine.sample_rate as f64;
+                    while rendered + chunk_time <= start.elapsed().as_secs_f64() {
+                        state.render(&mut buffer, 2);
+                        rendered += chunk_time;
+                    }
+                }
+            })
+        };
+
+        Self {
+            running,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for NullSink {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
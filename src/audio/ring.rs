@@ -0,0 +1,89 @@
+// Bounded single producer single consumer queue, pushing and popping never allocate or block
+use std::cell::{Cell, UnsafeCell};
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct Ring<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    read: AtomicUsize,  // only written by the consumer
+    write: AtomicUsize, // only written by the producer
+}
+
+// Slots are only accessed by one side at a time, handed over by the read and write indices
+unsafe impl<T: Send> Send for Ring<T> {}
+unsafe impl<T: Send> Sync for Ring<T> {}
+
+impl<T> Drop for Ring<T> {
+    fn drop(&mut self) {
+        let write = *self.write.get_mut();
+        let mut read = *self.read.get_mut();
+        while read != write {
+            unsafe {
+                self.slots[read % self.slots.len()]
+                    .get_mut()
+                    .assume_init_drop()
+            };
+            read = read.wrapping_add(1);
+        }
+    }
+}
+
+pub struct Producer<T> {
+    ring: Arc<Ring<T>>,
+    _not_sync: PhantomData<Cell<()>>, // push takes &self, so only one thread may hold it
+}
+
+pub struct Consumer<T> {
+    ring: Arc<Ring<T>>,
+}
+
+pub fn ring<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    let ring = Arc::new(Ring {
+        slots: (0..capacity.max(1))
+            .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+            .collect(),
+        read: AtomicUsize::new(0),
+        write: AtomicUsize::new(0),
+    });
+
+    (
+        Producer {
+            ring: ring.clone(),
+            _not_sync: PhantomData,
+        },
+        Consumer { ring },
+    )
+}
+
+impl<T> Producer<T> {
+    // Hands the value back when the queue is full
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let ring = &*self.ring;
+        let write = ring.write.load(Ordering::Relaxed);
+        let read = ring.read.load(Ordering::Acquire);
+        if write.wrapping_sub(read) == ring.slots.len() {
+            return Err(value);
+        }
+
+        unsafe { (*ring.slots[write % ring.slots.len()].get()).write(value) };
+        ring.write.store(write.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+}
+
+impl<T> Consumer<T> {
+    pub fn pop(&mut self) -> Option<T> {
+        let ring = &*self.ring;
+        let read = ring.read.load(Ordering::Relaxed);
+        let write = ring.write.load(Ordering::Acquire);
+        if read == write {
+            return None;
+        }
+
+        let value = unsafe { (*ring.slots[read % ring.slots.len()].get()).assume_init_read() };
+        ring.read.store(read.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+}
@@ -0,0 +1,290 @@
+// Text music format, see assets/music/level0.txt for an example
+use crate::audio::layers::{Layer, LayerRule};
+use crate::audio::notes::note_from_name;
+use crate::audio::sound::{
+    Envelope, Filter, FilterState, FilterType, Music, Track, WaveFn, wave_from_name,
+};
+
+#[derive(Clone, Copy)]
+struct Instrument {
+    wave: WaveFn,
+    volume: f64,
+    envelope: Envelope,
+    filter: Option<Filter>,
+}
+
+struct Pattern {
+    bars: usize,
+    notes: Vec<f64>,
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str, line: usize) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("line {}: invalid number \"{}\"", line, value))
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+fn parse_instrument(args: &[&str], line: usize) -> Result<Instrument, String> {
+    let mut instrument = Instrument {
+        wave: wave_from_name("triangle").unwrap(),
+        volume: 0.1,
+        envelope: Envelope::default(),
+        filter: None,
+    };
+    let mut filter = Filter {
+        filter_type: FilterType::LowPass,
+        cutoff: 1000.0,
+        resonance: 0.0,
+    };
+    let mut has_filter = false;
+
+    for arg in args {
+        let (key, value) = arg
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected key=value, got \"{}\"", line, arg))?;
+        match key {
+            "wave" => {
+                instrument.wave = wave_from_name(value)
+                    .ok_or_else(|| format!("line {}: unknown wave \"{}\"", line, value))?
+            }
+            "volume" => instrument.volume = parse_number(value, line)?,
+            "attack" => instrument.envelope.attack = parse_number(value, line)?,
+            "decay" => instrument.envelope.decay = parse_number(value, line)?,
+            "sustain" => instrument.envelope.sustain = parse_number(value, line)?,
+            "release" => instrument.envelope.release = parse_number(value, line)?,
+            "filter" => {
+                has_filter = true;
+                filter.filter_type = match value {
+                    "lowpass" => FilterType::LowPass,
+                    "highpass" => FilterType::HighPass,
+                    "bandpass" => FilterType::BandPass,
+                    _ => return Err(format!("line {}: unknown filter \"{}\"", line, value)),
+                };
+            }
+            "cutoff" => filter.cutoff = parse_number(value, line)?,
+            "resonance" => filter.resonance = parse_number(value, line)?,
+            _ => {
+                return Err(format!(
+                    "line {}: unknown instrument setting \"{}\"",
+                    line, key
+                ));
+            }
+        }
+    }
+
+    if has_filter {
+        instrument.filter = Some(filter);
+    }
+    Ok(instrument)
+}
+
+impl Music {
+    pub fn load(path: &str) -> Self {
+        let text = std::fs::read_to_string(path)
+            .unwrap_or_else(|err| panic!("Failed to load music: \"{}\". Error: {}", path, err));
+        Self::parse(&text)
+            .unwrap_or_else(|err| panic!("Failed to load music: \"{}\". Error: {}", path, err))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut tempo = 120.0;
+        let mut bar_length = 4;
+        let mut loop_points = None;
+
+        let mut instruments: Vec<(String, Instrument)> = Vec::new();
+        let mut patterns: Vec<(String, Pattern)> = Vec::new();
+        let mut tracks = Vec::new();
+        let mut layer_rules = Vec::new();
+
+        // Notes on the lines following a pattern header belong to that pattern
+        let mut current_pattern: Option<usize> = None;
+
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.split('#').next().unwrap_or_default();
+            let tokens = line.split_whitespace().collect::<Vec<_>>();
+            let Some((&keyword, args)) = tokens.split_first() else {
+                continue;
+            };
+
+            let expect_args = |count: usize| {
+                if args.len() < count {
+                    Err(format!(
+                        "line {}: \"{}\" expects {} arguments",
+                        line_number, keyword, count
+                    ))
+                } else {
+                    Ok(())
+                }
+            };
+
+            match keyword {
+                "tempo" => {
+                    expect_args(1)?;
+                    let bpm: f64 = parse_number(args[0], line_number)?;
+                    if bpm <= 0.0 {
+                        return Err(format!("line {}: tempo has to be above 0", line_number));
+                    }
+                    tempo = bpm / 60.0;
+                    current_pattern = None;
+                }
+                "bar" => {
+                    expect_args(1)?;
+                    bar_length = parse_number(args[0], line_number)?;
+                    if bar_length == 0 {
+                        return Err(format!("line {}: bar has to be above 0", line_number));
+                    }
+                    current_pattern = None;
+                }
+                "loop" => {
+                    expect_args(2)?;
+                    loop_points = Some((
+                        parse_number(args[0], line_number)?,
+                        parse_number(args[1], line_number)?,
+                    ));
+                    current_pattern = None;
+                }
+                "instrument" => {
+                    expect_args(1)?;
+                    let instrument = parse_instrument(&args[1..], line_number)?;
+                    instruments.push((args[0].to_owned(), instrument));
+                    current_pattern = None;
+                }
+                "pattern" => {
+                    expect_args(2)?;
+                    let bars = parse_number(args[1], line_number)?;
+                    patterns.push((
+                        args[0].to_owned(),
+                        Pattern {
+                            bars,
+                            notes: Vec::new(),
+                        },
+                    ));
+                    current_pattern = Some(patterns.len() - 1);
+                }
+                "layer" => {
+                    let rule = LayerRule::parse(args)
+                        .map_err(|err| format!("line {}: layer {}", line_number, err))?;
+                    layer_rules.push((line_number, rule));
+                    current_pattern = None;
+                }
+                "channel" => {
+                    expect_args(3)?;
+                    let instrument = instruments
+                        .iter()
+                        .find(|(name, _)| name == args[1])
+                        .map(|(_, instrument)| *instrument)
+                        .ok_or_else(|| {
+                            format!("line {}: unknown instrument \"{}\"", line_number, args[1])
+                        })?;
+
+                    let mut channel_patterns = Vec::new();
+                    for pattern_name in &args[2..] {
+                        let pattern = patterns
+                            .iter()
+                            .find(|(name, _)| name == pattern_name)
+                            .map(|(_, pattern)| pattern)
+                            .ok_or_else(|| {
+                                format!(
+                                    "line {}: unknown pattern \"{}\"",
+                                    line_number, pattern_name
+                                )
+                            })?;
+                        if pattern.notes.is_empty() || pattern.bars == 0 {
+                            return Err(format!(
+                                "line {}: pattern \"{}\" is empty",
+                                line_number, pattern_name
+                            ));
+                        }
+                        channel_patterns.push(pattern);
+                    }
+
+                    // Patterns can have different note lengths, stretch them all to the finest one
+                    let steps_per_bar = channel_patterns
+                        .iter()
+                        .map(|p| p.notes.len() / gcd(p.notes.len(), p.bars))
+                        .fold(1, |a, b| a / gcd(a, b) * b);
+
+                    let mut melody = Vec::new();
+                    let mut bars = 0;
+                    for pattern in channel_patterns {
+                        let steps = steps_per_bar * pattern.bars;
+                        melody.extend(
+                            (0..steps)
+                                .map(|step| pattern.notes[step * pattern.notes.len() / steps]),
+                        );
+                        bars += pattern.bars;
+                    }
+
+                    tracks.push(Track {
+                        name: args[0].to_owned(),
+                        wave: instrument.wave,
+                        length: bars * bar_length,
+                        melody,
+                        volume: instrument.volume,
+                        envelope: instrument.envelope,
+                        filter: instrument.filter,
+                        filter_state: FilterState::default(),
+                    });
+                    current_pattern = None;
+                }
+                _ => {
+                    let Some(pattern) = current_pattern else {
+                        return Err(format!(
+                            "line {}: unknown keyword \"{}\"",
+                            line_number, keyword
+                        ));
+                    };
+                    for token in tokens {
+                        let note = note_from_name(token).ok_or_else(|| {
+                            format!("line {}: invalid note \"{}\"", line_number, token)
+                        })?;
+                        patterns[pattern].1.notes.push(note);
+                    }
+                }
+            }
+        }
+
+        if tracks.is_empty() {
+            return Err("music has no channels".to_owned());
+        }
+
+        // Layer rules can name channels declared further down
+        let mut rules = Vec::new();
+        for (line_number, (channel, mut rule)) in layer_rules {
+            if channel != "*" {
+                let track = tracks
+                    .iter()
+                    .position(|t| t.name == channel)
+                    .ok_or_else(|| {
+                        format!("line {}: unknown channel \"{}\"", line_number, channel)
+                    })?;
+                rule.track = Some(track);
+            }
+            rules.push(rule);
+        }
+
+        // Without any rules every channel just plays
+        if rules.is_empty() {
+            rules.push(LayerRule::parse(&["*", "1", "fade=0"])?.1);
+        }
+
+        let longest_track = tracks.iter().map(|t| t.length).max().unwrap_or(0);
+        let (loop_start, loop_end) = loop_points.unwrap_or((0, longest_track.div_ceil(bar_length)));
+
+        Ok(Self {
+            tempo,
+            bar_length,
+            loop_start,
+            loop_end,
+            layers: vec![Layer::default(); tracks.len()],
+            tracks,
+            rules,
+            beat: 0.0,
+        })
+    }
+}
@@ -1,22 +1,118 @@
+use crate::audio::layers::{Layer, LayerRule, MusicState, update_layers};
 use crate::audio::notes::*;
+use crate::audio::wav::Sample;
 use glam::*;
 use interp::{InterpMode, interp};
 
-struct MusicSettings;
+pub type WaveFn = fn(f64, f64, f64) -> f64; // time, frequency, sample duration
 
-impl MusicSettings {
-    pub const TEMPO: f64 = 120. / 60.; // beats per second
-    pub const BAR_LENGTH: usize = 4; // beats per bar
-    pub const LOOP_LENGTH: usize = 8; // bars per loop
+pub const WAVES: [(&str, WaveFn); 5] = [
+    ("triangle", triangle_wave),
+    ("square", square_wave),
+    ("sawtooth", sawtooth_wave),
+    ("sine", sine_wave),
+    ("noise", white_noise),
+];
+
+pub fn wave_from_name(name: &str) -> Option<WaveFn> {
+    WAVES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, wave)| *wave)
 }
 
-type WaveFn = fn(f64, f64) -> f64;
+#[derive(Debug, Clone, Copy)]
+pub struct Envelope {
+    pub attack: f64,  // seconds to reach full volume
+    pub decay: f64,   // seconds to fall to the sustain level
+    pub sustain: f64, // volume while the note is held
+    pub release: f64, // seconds to fade out after the note ends
+}
+
+impl Default for Envelope {
+    // Just enough fading to not click
+    fn default() -> Self {
+        Self {
+            attack: 0.005,
+            decay: 0.0,
+            sustain: 1.0,
+            release: 0.01,
+        }
+    }
+}
+
+impl Envelope {
+    // Volume dt seconds after the note started, for a note that is held for gate seconds
+    pub fn amplitude(&self, dt: f64, gate: f64) -> f64 {
+        let held = |dt: f64| {
+            if dt < self.attack {
+                dt / self.attack
+            } else if dt < self.attack + self.decay {
+                1.0 - (1.0 - self.sustain) * (dt - self.attack) / self.decay
+            } else {
+                self.sustain
+            }
+        };
+
+        if dt < gate {
+            held(dt)
+        } else if dt < gate + self.release {
+            held(gate) * (1.0 - (dt - gate) / self.release)
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterType {
+    LowPass,
+    HighPass,
+    BandPass,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Filter {
+    pub filter_type: FilterType,
+    pub cutoff: f64,    // frequency in Hz
+    pub resonance: f64, // 0.0 is none, close to 1.0 rings
+}
+
+// State variable filter, needs to be kept per voice
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FilterState {
+    low: f64,
+    band: f64,
+}
+
+impl FilterState {
+    pub fn process(&mut self, input: f64, filter: &Filter, sample_duration: f64) -> f64 {
+        // Keep the cutoff in the stable range of the filter
+        let cutoff = filter.cutoff.min(0.16 / sample_duration);
+        let f = 2.0 * (std::f64::consts::PI * cutoff * sample_duration).sin();
+        let damping = 2.0 * (1.0 - filter.resonance.clamp(0.0, 0.99));
+
+        self.low += f * self.band;
+        let high = input - self.low - damping * self.band;
+        self.band += f * high;
+
+        match filter.filter_type {
+            FilterType::LowPass => self.low,
+            FilterType::HighPass => high,
+            FilterType::BandPass => self.band,
+        }
+    }
+}
 
 pub struct Track {
+    pub name: String,
     pub wave: WaveFn,
-    pub length: usize, // number of bars per track
-    pub melody: &'static [f64],
+    pub length: usize, // number of beats per track
+    pub melody: Vec<f64>,
     pub volume: f64,
+    pub envelope: Envelope,
+    pub filter: Option<Filter>,
+    pub filter_state: FilterState,
 }
 
 pub struct Sound {
@@ -26,125 +122,128 @@ pub struct Sound {
     pub interval: f64, // interval in seconds
     pub melody: &'static [f64],
     pub volume: f64,
+    pub envelope: Envelope, // applied over the whole duration
+    pub filter: Option<Filter>,
 }
 
+// Loaded from a music file, see `Music::load`
 pub struct Music {
+    pub tempo: f64,        // beats per second
+    pub bar_length: usize, // beats per bar
+    pub loop_start: usize, // in bars
+    pub loop_end: usize,   // in bars
     pub tracks: Vec<Track>,
-    pub track_mask: Vec<u32>,
+    pub rules: Vec<LayerRule>,
+    pub layers: Vec<Layer>, // one per track
+    pub beat: f64,          // playback position
 }
 
-impl Default for Music {
-    fn default() -> Self {
-        Self::new()
+impl Music {
+    // Current beat in the song, wrapped back to the loop start after the loop end
+    pub fn beat_in_loop(&self, beat: f64) -> f64 {
+        let loop_start = (self.loop_start * self.bar_length) as f64;
+        let loop_end = (self.loop_end * self.bar_length) as f64;
+        if beat < loop_end || loop_end <= loop_start {
+            beat
+        } else {
+            loop_start + (beat - loop_start) % (loop_end - loop_start)
+        }
     }
-}
 
-impl Music {
-    pub fn new() -> Self {
-        let melody_track = Track {
-            wave: triangle_wave,
-            length: 8 * MusicSettings::BAR_LENGTH,
-            melody: &[
-                D4, D4, D4, A3, C4, C4, C4, A3, G3, G3, G3, F3, A3, A3, A3, REST, D4, D4, D4, A3,
-                C4, C4, C4, A3, G3, G3, G3, F3, D3, D3, D3, REST,
-            ],
-            volume: 0.1,
-        };
+    pub fn update_layers(&mut self, state: &MusicState, time: f64) {
+        update_layers(
+            &mut self.rules,
+            &mut self.layers,
+            state,
+            time,
+            self.beat,
+            self.tempo,
+            self.bar_length,
+        );
+    }
 
-        let contramelody_track = Track {
-            wave: sine_wave,
-            length: 8 * MusicSettings::BAR_LENGTH,
-            melody: &[
-                A4, A4, A4, F4, G4, G4, G4, D4, F4, F4, F4, CS4, E4, E4, E4, REST, A4, A4, A4, F4,
-                G4, G4, G4, D4, F4, F4, F4, G4, A4, A4, A4, REST,
-            ],
-            volume: 0.1,
-        };
+    // Back to the start with every layer silent
+    pub fn reset(&mut self) {
+        self.beat = 0.0;
+        self.layers.fill(Layer::default());
+        for rule in self.rules.iter_mut() {
+            rule.reset();
+        }
+    }
 
-        let bass_track = Track {
-            wave: square_wave,
-            length: 8 * MusicSettings::BAR_LENGTH,
-            melody: &[
-                REST, REST, D2, D2, REST, D2, D2, REST, D2, D2, REST, REST, REST, REST, REST, REST,
-                REST, REST, E2, E2, REST, E2, E2, REST, E2, E2, REST, REST, REST, REST, REST, REST,
-                REST, REST, G2, G2, REST, G2, G2, REST, G2, G2, REST, REST, REST, REST, REST, REST,
-                REST, REST, A2, A2, REST, A2, A2, REST, A2, A2, REST, REST, G2, G2, G2, REST, REST,
-                REST, D2, D2, REST, D2, D2, REST, D2, D2, REST, REST, REST, REST, REST, REST, REST,
-                REST, E2, E2, REST, E2, E2, REST, E2, E2, REST, REST, REST, REST, REST, REST, REST,
-                REST, G2, G2, REST, G2, G2, REST, G2, G2, REST, REST, CS2, CS2, CS2, REST, D2, D2,
-                REST, D2, D2, REST, D2, D2, D2, REST, REST, REST, REST, REST, REST, REST,
-            ],
-            volume: 0.1,
-        };
+    pub fn is_playing(&self) -> bool {
+        !self.layers.iter().all(Layer::is_silent)
+    }
+}
 
-        let accent_track = Track {
-            wave: triangle_wave,
-            length: 4 * MusicSettings::BAR_LENGTH,
-            melody: &[
-                REST, REST, D5, REST, D5, REST, C5, A4, REST, REST, REST, REST, REST, REST, REST,
-                REST, REST, REST, A4, REST, A4, REST, C5, D5, REST, REST, REST, REST, REST, REST,
-                REST, REST, REST, REST, D5, REST, D5, REST, F5, D5, REST, REST, REST, REST, REST,
-                REST, REST, REST, REST, REST, C5, REST, C5, REST, B4, A4, REST, REST, REST, REST,
-                REST, REST, REST, REST,
-            ],
-            volume: 0.1,
-        };
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SoundId(pub u32);
 
-        let snare_track = Track {
-            wave: white_noise,
-            length: 4 * MusicSettings::BAR_LENGTH,
-            melody: &[
-                REST, REST, REST, REST, C3, REST, REST, REST, REST, REST, REST, REST, C3, REST,
-                REST, REST, REST, REST, REST, REST, C3, REST, REST, REST, REST, REST, REST, REST,
-                C3, REST, REST, REST, REST, REST, REST, REST, C3, REST, REST, REST, REST, REST,
-                REST, REST, C3, REST, REST, REST, REST, REST, REST, REST, C3, REST, REST, REST,
-                REST, REST, C3, REST, C3, REST, REST, REST,
-            ],
-            volume: 0.1,
-        };
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SongId(pub u32);
 
-        Self {
-            tracks: vec![
-                bass_track,
-                melody_track,
-                contramelody_track,
-                accent_track,
-                snare_track,
-            ],
-            track_mask: vec![0, 0, 0, 0, 0],
+pub enum SoundEffect {
+    Synth(Sound),
+    Sample { sample: Sample, volume: f64 },
+}
+
+impl SoundEffect {
+    // Looping sounds (with an interval) only stop when they are explicitly stopped
+    pub fn is_finished(&self, dt: f64) -> bool {
+        match self {
+            Self::Synth(sound) => sound.interval == 0.0 && dt >= sound.duration,
+            Self::Sample { sample, .. } => dt >= sample.duration(),
         }
     }
-}
 
-pub enum SoundTypes {
-    FootstepSound,
-    JumpSound,
-    DeathSound,
-    PickupSound,
+    // Returns the (left, right) signal at time t for a sound started at t0
+    pub fn play(
+        &self,
+        t: f64,
+        t0: f64,
+        sample_duration: f64,
+        filter_state: &mut FilterState,
+    ) -> (f64, f64) {
+        match self {
+            Self::Synth(sound) => {
+                let value = play_sfx(t, t0, sound, sample_duration, filter_state);
+                (value, value)
+            }
+            Self::Sample { sample, volume } => {
+                let (left, right) = sample.play(t - t0);
+                (left * volume, right * volume)
+            }
+        }
+    }
 }
 
+// To add a sound effect, add it to the list below and trigger it by name
 pub struct SoundEffects {
-    pub footstep: Sound,
-    pub jump: Sound,
-    pub death: Sound,
-    pub pickup: Sound,
-}
-
-impl Default for SoundEffects {
-    fn default() -> Self {
-        Self::new()
-    }
+    pub sounds: Vec<SoundEffect>,
+    pub names: Vec<&'static str>,
 }
 
 impl SoundEffects {
-    pub fn new() -> Self {
+    // Samples get resampled to the sample rate of the output device
+    pub fn new(sample_rate: u32) -> Self {
         let footstep = Sound {
             wave: triangle_wave,
             start: 0.,
             duration: 0.15,
-            interval: 0.3,
+            interval: 0.0, // played on the footstep frames of the walk animation
             melody: &[D2],
             volume: 0.1,
+            envelope: Envelope {
+                attack: 0.005,
+                decay: 0.1,
+                sustain: 0.0,
+                release: 0.01,
+            },
+            filter: Some(Filter {
+                filter_type: FilterType::LowPass,
+                cutoff: 400.0,
+                resonance: 0.0,
+            }),
         };
 
         let jump = Sound {
@@ -154,6 +253,8 @@ impl SoundEffects {
             interval: 0.0,
             melody: &[C4, E4, G4, C5],
             volume: 0.1,
+            envelope: Envelope::default(),
+            filter: None,
         };
 
         let death = Sound {
@@ -165,6 +266,17 @@ impl SoundEffects {
                 D2, A1, REST, C2, G1, REST, A1, F1, REST, REST, D1, REST, D1, D1, D1, REST,
             ],
             volume: 0.1,
+            envelope: Envelope {
+                attack: 0.005,
+                decay: 0.0,
+                sustain: 1.0,
+                release: 0.3,
+            },
+            filter: Some(Filter {
+                filter_type: FilterType::LowPass,
+                cutoff: 1200.0,
+                resonance: 0.2,
+            }),
         };
 
         let pickup = Sound {
@@ -174,91 +286,155 @@ impl SoundEffects {
             interval: 0.0,
             melody: &[CS5, A4, CS5, E5, A5],
             volume: 0.1,
+            envelope: Envelope {
+                attack: 0.005,
+                decay: 0.0,
+                sustain: 1.0,
+                release: 0.1,
+            },
+            filter: None,
         };
 
-        Self {
-            footstep,
-            jump,
-            death,
-            pickup,
-        }
-    }
-}
+        let enemy_step = Sound {
+            wave: white_noise,
+            start: 0.,
+            duration: 0.05,
+            interval: 0.0,
+            melody: &[C3],
+            volume: 0.08,
+            envelope: Envelope {
+                attack: 0.001,
+                decay: 0.04,
+                sustain: 0.0,
+                release: 0.01,
+            },
+            filter: Some(Filter {
+                filter_type: FilterType::BandPass,
+                cutoff: 900.0,
+                resonance: 0.3,
+            }),
+        };
 
-pub fn play_music(t: f64, t0: &DVec4, color_mask: &UVec3, music: &mut Music) -> f64 {
-    let mut signal = 0.0;
+        let chime = Sound {
+            wave: sine_wave,
+            start: 0.,
+            duration: 0.6,
+            interval: 0.0,
+            melody: &[B4, E5, B5, REST],
+            volume: 0.08,
+            envelope: Envelope {
+                attack: 0.002,
+                decay: 0.1,
+                sustain: 0.3,
+                release: 0.05,
+            },
+            filter: None,
+        };
 
-    // start either 1 second after red mask pickup or 3 seconds after death
-    let tstart = if t0[0] > 0.0 {
-        (t0[0] + 1.).max(t0[3] + 3.)
-    } else {
-        0.0
-    };
+        let stinger = Sound {
+            wave: square_wave,
+            start: 0.,
+            duration: 0.5,
+            interval: 0.0,
+            melody: &[D5, A4, D5, F5, A5, A5, A5, REST],
+            volume: 0.06,
+            envelope: Envelope {
+                attack: 0.005,
+                decay: 0.05,
+                sustain: 0.6,
+                release: 0.05,
+            },
+            filter: Some(Filter {
+                filter_type: FilterType::LowPass,
+                cutoff: 2500.0,
+                resonance: 0.1,
+            }),
+        };
 
-    if tstart > 0.0 && t > tstart {
-        let beat_in_game = (t - tstart) * MusicSettings::TEMPO; // total beats since red mask pickup
-        let beats_since_blue = (t - (t0[2].max(t0[3] + 3.))) * MusicSettings::TEMPO; // total beats since red mask pickup
+        let test_sound = SoundEffect::Sample {
+            sample: Sample::load("assets/test_sound.wav").resample(sample_rate),
+            volume: 0.1,
+        };
 
-        let beat_in_loop =
-            beat_in_game % (MusicSettings::BAR_LENGTH * MusicSettings::LOOP_LENGTH) as f64; // current beat in the loop
+        let sound_effects = [
+            ("footstep", SoundEffect::Synth(footstep)),
+            ("jump", SoundEffect::Synth(jump)),
+            ("death", SoundEffect::Synth(death)),
+            ("pickup", SoundEffect::Synth(pickup)),
+            ("stinger", SoundEffect::Synth(stinger)),
+            ("enemy_step", SoundEffect::Synth(enemy_step)),
+            ("chime", SoundEffect::Synth(chime)),
+            ("test_sound", test_sound),
+        ];
 
-        if beat_in_game < 0.1 {
-            music.track_mask = vec![0, 0, 0, 0, 0];
+        Self {
+            names: sound_effects.iter().map(|(name, _)| *name).collect(),
+            sounds: sound_effects.into_iter().map(|(_, sound)| sound).collect(),
         }
+    }
 
-        // green melody
-        if t0[1] > 0.0 {
-            if t - t0[1] < 1. {
-                music.track_mask[3] = color_mask[1];
-            } else {
-                music.track_mask[3] = 256;
-            }
-
-            // always play red music
-            music.track_mask[0] = 256;
-            music.track_mask[4] = 256;
+    pub fn find(&self, name: &str) -> Option<SoundId> {
+        self.names
+            .iter()
+            .position(|&n| n == name)
+            .map(|i| SoundId(i as u32))
+    }
+}
 
-            // always play blue music
-            music.track_mask[1] = 256;
-            music.track_mask[2] = 256;
-        } else if t0[2] > 0.0 {
-            if t - t0[2] < 1. {
-                music.track_mask[1] = color_mask[1];
-            } else {
-                music.track_mask[1] = 256;
-            }
+pub fn play_music(t: f64, music: &mut Music, sample_duration: f64) -> f64 {
+    let mut signal = 0.0;
 
-            // add second melody after 8 bars
-            if beats_since_blue > 32. {
-                music.track_mask[2] = 256;
-            }
+    // The song starts over once every layer faded out, so there is no beat to wait for
+    if !music.is_playing() {
+        music.beat = 0.0;
+        for layer in music.layers.iter_mut() {
+            layer.start_pending();
+        }
+    }
 
-            // always play red music
-            music.track_mask[0] = 256;
-            music.track_mask[4] = 256;
-        } else if t0[0] > 0.0 {
-            if t - t0[0] < 1. {
-                music.track_mask[0] = color_mask[0];
-            } else {
-                music.track_mask[0] = 256;
-            }
+    for layer in music.layers.iter_mut() {
+        layer.advance(sample_duration, music.beat);
+    }
 
-            // add snare after 8 bars
-            if beat_in_game > 32. {
-                music.track_mask[4] = 256;
-            }
-        }
+    if music.is_playing() {
+        let beat_in_loop = music.beat_in_loop(music.beat); // current beat in the loop
+        music.beat += sample_duration * music.tempo;
 
-        for (track, &track_mask) in music.tracks.iter().zip(music.track_mask.iter()) {
-            if track_mask > 0 {
+        let tempo = music.tempo;
+        for (track, layer) in music.tracks.iter_mut().zip(music.layers.iter()) {
+            if layer.volume > 0.0 {
                 let beat_in_track = beat_in_loop % track.length as f64;
-                let idx_in_track = (beat_in_track / track.length as f64 * track.melody.len() as f64)
-                    .floor() as usize;
+                let note_length = track.length as f64 / track.melody.len() as f64; // in beats
+                let idx_in_track = (beat_in_track / note_length).floor() as usize;
                 let note = track.melody[idx_in_track];
+
+                let mut value = 0.0;
                 if note != REST {
-                    let volume = track.volume * track_mask as f64 / 256.;
-                    signal += volume * (track.wave)(t, note);
+                    // Repeated notes are tied together into one long note
+                    let first = track.melody[..idx_in_track]
+                        .iter()
+                        .rposition(|&n| n != note)
+                        .map_or(0, |i| i + 1);
+                    let last = track.melody[idx_in_track..]
+                        .iter()
+                        .position(|&n| n != note)
+                        .map_or(track.melody.len(), |i| idx_in_track + i);
+
+                    let dt = (beat_in_track - first as f64 * note_length) / tempo;
+                    let length = (last - first) as f64 * note_length / tempo;
+                    let gate = (length - track.envelope.release).max(0.0);
+
+                    let volume = track.volume * layer.volume;
+                    value = volume
+                        * track.envelope.amplitude(dt, gate)
+                        * (track.wave)(t, note, sample_duration);
+                }
+
+                // Keep filtering during rests so the filter can ring out
+                if let Some(filter) = &track.filter {
+                    value = track.filter_state.process(value, filter, sample_duration);
                 }
+                signal += value;
             }
         }
     }
@@ -266,24 +442,57 @@ pub fn play_music(t: f64, t0: &DVec4, color_mask: &UVec3, music: &mut Music) ->
     signal
 }
 
-pub fn play_sfx(t: f64, t0: f64, sound: &Sound) -> f64 {
+pub fn play_sfx(
+    t: f64,
+    t0: f64,
+    sound: &Sound,
+    sample_duration: f64,
+    filter_state: &mut FilterState,
+) -> f64 {
     let dt = if sound.interval == 0.0 {
         t - t0
     } else {
         (t - t0) % sound.interval
     };
 
-    if sound.duration > dt {
+    let value = if sound.duration > dt {
         let idx_in_melody = (dt / sound.duration * sound.melody.len() as f64) as usize;
         let note = sound.melody[idx_in_melody];
-        sound.volume * (sound.wave)(t, note)
+        let gate = (sound.duration - sound.envelope.release).max(0.0);
+        if note != REST {
+            sound.volume
+                * sound.envelope.amplitude(dt, gate)
+                * (sound.wave)(t, note, sample_duration)
+        } else {
+            0.0
+        }
+    } else {
+        0.0
+    };
+
+    if let Some(filter) = &sound.filter {
+        filter_state.process(value, filter, sample_duration)
+    } else {
+        value
+    }
+}
+
+// Smooths out the discontinuity of a wave at phase 0, dt is the phase increment per sample
+fn poly_blep(phase: f64, dt: f64) -> f64 {
+    if phase < dt {
+        let x = phase / dt;
+        2.0 * x - x * x - 1.0
+    } else if phase > 1.0 - dt {
+        let x = (phase - 1.0) / dt;
+        x * x + 2.0 * x + 1.0
     } else {
         0.0
     }
 }
 
-pub fn triangle_wave(t: f64, freq: f64) -> f64 {
-    let saw = 2. * sawtooth_wave(t, freq);
+pub fn triangle_wave(t: f64, freq: f64, _sample_duration: f64) -> f64 {
+    // The harmonics of a triangle fall off fast enough to not need band-limiting
+    let saw = 4. * (freq * t).fract() - 2.;
     if saw > 1. {
         2. - saw
     } else if saw < -1. {
@@ -293,12 +502,17 @@ pub fn triangle_wave(t: f64, freq: f64) -> f64 {
     }
 }
 
-pub fn square_wave(t: f64, freq: f64) -> f64 {
-    2. * (((freq * t * 2.) as i32) % 2) as f64 - 1.
+pub fn square_wave(t: f64, freq: f64, sample_duration: f64) -> f64 {
+    let phase = (freq * t).fract();
+    let dt = freq * sample_duration;
+    let naive = if phase < 0.5 { -1. } else { 1. };
+    naive - poly_blep(phase, dt) + poly_blep((phase + 0.5).fract(), dt)
 }
 
-pub fn sawtooth_wave(t: f64, freq: f64) -> f64 {
-    2. * (freq * t) % 2. - 1.
+pub fn sawtooth_wave(t: f64, freq: f64, sample_duration: f64) -> f64 {
+    let phase = (freq * t).fract();
+    let dt = freq * sample_duration;
+    2. * phase - 1. - poly_blep(phase, dt)
 }
 
 pub fn custom_wave(t: f64, freq: f64, ttable: &[f64], ytable: &[f64]) -> f64 {
@@ -337,7 +551,7 @@ pub const SINEYTABLE: [f64; 21] = [
     0.000_000_000_000,
 ];
 
-pub fn sine_wave(t: f64, freq: f64) -> f64 {
+pub fn sine_wave(t: f64, freq: f64, _sample_duration: f64) -> f64 {
     custom_wave(t, freq, &SINETTABLE, &SINEYTABLE)
 }
 
@@ -349,8 +563,8 @@ fn wang_hash(seed: u32) -> u32 {
     seed ^ (seed >> 15)
 }
 
-pub fn white_noise(t: f64, _freq: f64) -> f64 {
-    let rand_u32 = wang_hash((t * 44_100.) as u32);
+pub fn white_noise(t: f64, _freq: f64, sample_duration: f64) -> f64 {
+    let rand_u32 = wang_hash((t / sample_duration) as u32);
     let rand_0_1 = (rand_u32 as f64) / (u32::MAX as f64);
     1. - 2. * rand_0_1
 }
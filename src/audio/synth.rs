@@ -0,0 +1,136 @@
+// Playable polyphonic synth, driven by the jam mode of the game
+use crate::audio::notes::note_from_midi;
+use crate::audio::sound::{Envelope, WAVES, WaveFn};
+
+pub const MAX_SYNTH_VOICES: usize = 16;
+
+// Also written to exported jams, so they sound the same in the music file
+pub const SYNTH_ENVELOPE: Envelope = Envelope {
+    attack: 0.01,
+    decay: 0.2,
+    sustain: 0.6,
+    release: 0.3,
+};
+pub const SYNTH_VOLUME: f64 = 0.2;
+
+pub enum SynthEvent {
+    NoteOn(i32), // midi note
+    NoteOff(i32),
+    Wave(usize), // index into `WAVES`
+    Sustain(bool),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SynthVoice {
+    note: i32,
+    frequency: f64,
+    start: f64,
+    released: Option<f64>, // time the note was let go
+    sustained: bool,       // let go while the sustain pedal was down
+}
+
+pub struct Synth {
+    voices: [Option<SynthVoice>; MAX_SYNTH_VOICES],
+    wave: WaveFn,
+    envelope: Envelope,
+    volume: f64,
+    sustain: bool,
+}
+
+impl Default for Synth {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Synth {
+    pub fn new() -> Self {
+        Self {
+            voices: [None; MAX_SYNTH_VOICES],
+            wave: WAVES[0].1,
+            envelope: SYNTH_ENVELOPE,
+            volume: SYNTH_VOLUME,
+            sustain: false,
+        }
+    }
+
+    pub fn handle(&mut self, event: SynthEvent, t: f64) {
+        match event {
+            SynthEvent::NoteOn(note) => {
+                // Retriggering a note reuses its voice, otherwise take a free or the oldest one
+                let slot = self
+                    .voices
+                    .iter()
+                    .position(|v| v.is_some_and(|v| v.note == note))
+                    .or_else(|| self.voices.iter().position(|v| v.is_none()))
+                    .unwrap_or_else(|| {
+                        self.voices
+                            .iter()
+                            .enumerate()
+                            .min_by(|(_, a), (_, b)| {
+                                let a = a.map_or(f64::MIN, |v| v.start);
+                                let b = b.map_or(f64::MIN, |v| v.start);
+                                a.total_cmp(&b)
+                            })
+                            .map_or(0, |(i, _)| i)
+                    });
+                self.voices[slot] = Some(SynthVoice {
+                    note,
+                    frequency: note_from_midi(note),
+                    start: t,
+                    released: None,
+                    sustained: false,
+                });
+            }
+            SynthEvent::NoteOff(note) => {
+                for voice in self.voices.iter_mut().flatten() {
+                    if voice.note == note && voice.released.is_none() {
+                        if self.sustain {
+                            voice.sustained = true;
+                        } else {
+                            voice.released = Some(t);
+                        }
+                    }
+                }
+            }
+            SynthEvent::Wave(index) => {
+                if let Some((_, wave)) = WAVES.get(index) {
+                    self.wave = *wave;
+                }
+            }
+            SynthEvent::Sustain(sustain) => {
+                self.sustain = sustain;
+                if !sustain {
+                    for voice in self.voices.iter_mut().flatten() {
+                        if voice.sustained && voice.released.is_none() {
+                            voice.released = Some(t);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn play(&mut self, t: f64, sample_duration: f64) -> f64 {
+        let mut signal = 0.0;
+
+        for slot in self.voices.iter_mut() {
+            let Some(voice) = slot else {
+                continue;
+            };
+
+            let dt = t - voice.start;
+            let gate = voice.released.map_or(f64::INFINITY, |r| r - voice.start);
+            if dt > gate + self.envelope.release {
+                *slot = None;
+                continue;
+            }
+
+            signal += self.volume
+                * self.envelope.amplitude(dt, gate)
+                * (self.wave)(t, voice.frequency, sample_duration);
+        }
+
+        signal
+    }
+}
@@ -0,0 +1,183 @@
+// Minimal RIFF/WAVE decoder, supports 8 and 16 bit PCM and 32 bit float in mono or stereo
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub sample_rate: u32,
+    pub frames: Vec<[f32; 2]>, // left, right
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+impl Sample {
+    pub(crate) fn load(path: &str) -> Self {
+        let bytes = std::fs::read(path)
+            .unwrap_or_else(|err| panic!("Failed to load sample: \"{}\". Error: {}", path, err));
+        Self::parse(&bytes)
+            .unwrap_or_else(|err| panic!("Failed to load sample: \"{}\". Error: {}", path, err))
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.get(0..4) != Some(b"RIFF") || bytes.get(8..12) != Some(b"WAVE") {
+            return Err("not a RIFF/WAVE file".to_owned());
+        }
+
+        let mut format = None;
+        let mut data = None;
+
+        let mut offset = 12;
+        while let (Some(id), Some(size)) =
+            (bytes.get(offset..offset + 4), read_u32(bytes, offset + 4))
+        {
+            let start = offset + 8;
+            let end = (start + size as usize).min(bytes.len());
+            match id {
+                b"fmt " => format = Some(&bytes[start..end]),
+                b"data" => data = Some(&bytes[start..end]),
+                _ => {}
+            }
+            // chunks are padded to an even size
+            offset = start + size as usize + (size as usize & 1);
+        }
+
+        let format = format.ok_or("missing fmt chunk")?;
+        let data = data.ok_or("missing data chunk")?;
+
+        let mut format_tag = read_u16(format, 0).ok_or("fmt chunk too small")?;
+        let channels = read_u16(format, 2).ok_or("fmt chunk too small")?;
+        let sample_rate = read_u32(format, 4).ok_or("fmt chunk too small")?;
+        let bits_per_sample = read_u16(format, 14).ok_or("fmt chunk too small")?;
+        if format_tag == WAVE_FORMAT_EXTENSIBLE {
+            // the first two bytes of the sub format guid hold the actual format
+            format_tag = read_u16(format, 24).ok_or("fmt chunk too small")?;
+        }
+
+        if channels != 1 && channels != 2 {
+            return Err(format!("unsupported channel count {}", channels));
+        }
+        if sample_rate == 0 {
+            return Err("invalid sample rate".to_owned());
+        }
+
+        let samples: Vec<f32> = match (format_tag, bits_per_sample) {
+            (WAVE_FORMAT_PCM, 8) => data.iter().map(|&s| (s as f32 - 128.0) / 128.0).collect(),
+            (WAVE_FORMAT_PCM, 16) => data
+                .chunks_exact(2)
+                .map(|s| i16::from_le_bytes([s[0], s[1]]) as f32 / 32768.0)
+                .collect(),
+            (WAVE_FORMAT_IEEE_FLOAT, 32) => data
+                .chunks_exact(4)
+                .map(|s| f32::from_le_bytes([s[0], s[1], s[2], s[3]]))
+                .collect(),
+            _ => {
+                return Err(format!(
+                    "unsupported format {} with {} bits per sample",
+                    format_tag, bits_per_sample
+                ));
+            }
+        };
+
+        let frames = if channels == 1 {
+            samples.iter().map(|&s| [s, s]).collect()
+        } else {
+            samples.chunks_exact(2).map(|s| [s[0], s[1]]).collect()
+        };
+
+        Ok(Self {
+            sample_rate,
+            frames,
+        })
+    }
+
+    // Linear resampling, good enough for sound effects
+    pub fn resample(&self, sample_rate: u32) -> Self {
+        if sample_rate == self.sample_rate || self.frames.is_empty() {
+            return Self {
+                sample_rate,
+                frames: self.frames.clone(),
+            };
+        }
+
+        let ratio = self.sample_rate as f64 / sample_rate as f64;
+        let frame_count = (self.frames.len() as f64 / ratio).floor() as usize;
+        let last = self.frames.len() - 1;
+
+        let frames = (0..frame_count)
+            .map(|i| {
+                let position = i as f64 * ratio;
+                let index = (position as usize).min(last);
+                let next = (index + 1).min(last);
+                let f = (position - index as f64) as f32;
+
+                let a = self.frames[index];
+                let b = self.frames[next];
+                [a[0] + (b[0] - a[0]) * f, a[1] + (b[1] - a[1]) * f]
+            })
+            .collect();
+
+        Self {
+            sample_rate,
+            frames,
+        }
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.frames.len() as f64 / self.sample_rate as f64
+    }
+
+    // Returns the (left, right) signal dt seconds after the start of the sample
+    pub fn play(&self, dt: f64) -> (f64, f64) {
+        let index = (dt * self.sample_rate as f64) as usize;
+        if let Some(frame) = self.frames.get(index) {
+            (frame[0] as f64, frame[1] as f64)
+        } else {
+            (0.0, 0.0)
+        }
+    }
+
+    pub fn store_to_file(&self, path: &str) {
+        std::fs::write(path, self.encode()).unwrap();
+    }
+
+    // 16 bit stereo PCM wave file
+    pub fn encode(&self) -> Vec<u8> {
+        let data_size = self.frames.len() as u32 * 4;
+
+        let mut data = Vec::with_capacity(44 + data_size as usize);
+        data.extend_from_slice(b"RIFF");
+        data.extend_from_slice(&(36 + data_size).to_le_bytes());
+        data.extend_from_slice(b"WAVE");
+
+        data.extend_from_slice(b"fmt ");
+        data.extend_from_slice(&16u32.to_le_bytes());
+        data.extend_from_slice(&WAVE_FORMAT_PCM.to_le_bytes());
+        data.extend_from_slice(&2u16.to_le_bytes()); // channels
+        data.extend_from_slice(&self.sample_rate.to_le_bytes());
+        data.extend_from_slice(&(self.sample_rate * 4).to_le_bytes()); // bytes per second
+        data.extend_from_slice(&4u16.to_le_bytes()); // bytes per frame
+        data.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+
+        data.extend_from_slice(b"data");
+        data.extend_from_slice(&data_size.to_le_bytes());
+        for frame in self.frames.iter() {
+            for s in frame {
+                let s = (s.clamp(-1.0, 1.0) * 32767.0) as i16;
+                data.extend_from_slice(&s.to_le_bytes());
+            }
+        }
+        data
+    }
+}
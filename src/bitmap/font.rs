@@ -1,6 +1,63 @@
+// Bitmap fonts, either the built in 5x5 font or loaded from a BMFont text file and its glyph
+// sheet, see assets/fonts/pixl.fnt
+use super::{Bitmap, WHITE};
+use glam::*;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Glyph {
+    pub x: i32, // in the glyph sheet
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub offset: IVec2, // from the pen position to the top left of the glyph
+    pub advance: i32,
+}
+
+// A line of laid out text, `start..end` are byte offsets into the text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextLine {
+    pub start: usize,
+    pub end: usize,
+    pub width: i32,
+}
+
 pub struct Font {
-    pub(crate) data: [[[bool; 5]; 5]; 256],
-    pub(crate) translation: [usize; 256],
+    pub sheet: Bitmap,                 // glyph coverage in the alpha channel
+    glyphs: Vec<(char, Glyph)>,        // sorted by character
+    kerning: Vec<((char, char), i32)>, // sorted by pair
+    fallback: Glyph,                   // drawn for characters the font doesn't have
+    pub line_height: i32,
+}
+
+fn parse_value<T: std::str::FromStr>(
+    values: &[(&str, &str)],
+    key: &str,
+    line: usize,
+) -> Result<T, String> {
+    let value = values
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| *v)
+        .ok_or_else(|| format!("line {}: missing \"{}\"", line, key))?;
+    value
+        .parse()
+        .map_err(|_| format!("line {}: invalid {} \"{}\"", line, key, value))
+}
+
+// Splits `key=value key="quoted value"` pairs
+fn parse_pairs(text: &str) -> Vec<(&str, &str)> {
+    let mut pairs = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some((key, after)) = rest.split_once('=') {
+        let (value, after) = if let Some(quoted) = after.strip_prefix('"') {
+            quoted.split_once('"').unwrap_or((quoted, ""))
+        } else {
+            after.split_once(char::is_whitespace).unwrap_or((after, ""))
+        };
+        pairs.push((key.trim(), value));
+        rest = after.trim_start();
+    }
+    pairs
 }
 
 impl Font {
@@ -66,27 +123,346 @@ impl Font {
             [0b10010, 0b10010, 0b10010, 0b11110, 0b10000], // micro
         ];
 
-        let mut data = [[[true; 5]; 5]; 256];
-        for (i, character) in CHARACTERS.iter().enumerate() {
-            #[allow(clippy::needless_range_loop)]
-            for y in 0..5 {
+        // Punctuation and Latin-1 characters that aren't a letter with an accent
+        const EXTRA_CHARACTERS: [(char, [u8; 5]); 49] = [
+            ('"', [0b01010, 0b01010, 0b00000, 0b00000, 0b00000]),
+            ('$', [0b01111, 0b10100, 0b01110, 0b00101, 0b11110]),
+            ('%', [0b11001, 0b11010, 0b00100, 0b01011, 0b10011]),
+            ('&', [0b01100, 0b10010, 0b01101, 0b10010, 0b01101]),
+            ('+', [0b00000, 0b00100, 0b01110, 0b00100, 0b00000]),
+            (';', [0b00000, 0b00100, 0b00000, 0b00100, 0b01000]),
+            ('@', [0b01110, 0b10001, 0b10111, 0b10110, 0b01111]),
+            ('\\', [0b10000, 0b01000, 0b00100, 0b00010, 0b00001]),
+            ('`', [0b01000, 0b00100, 0b00000, 0b00000, 0b00000]),
+            ('{', [0b00110, 0b00100, 0b01000, 0b00100, 0b00110]),
+            ('}', [0b01100, 0b00100, 0b00010, 0b00100, 0b01100]),
+            ('~', [0b00000, 0b01000, 0b10101, 0b00010, 0b00000]),
+            ('æ', [0b01111, 0b10100, 0b11111, 0b10100, 0b10111]),
+            ('ø', [0b01111, 0b10011, 0b10101, 0b11001, 0b11110]),
+            ('ß', [0b01110, 0b10001, 0b10110, 0b10001, 0b10110]),
+            ('ð', [0b11110, 0b10001, 0b11101, 0b10001, 0b11110]),
+            ('þ', [0b10000, 0b11110, 0b10001, 0b11110, 0b10000]),
+            ('¡', [0b00100, 0b00000, 0b00100, 0b00100, 0b00100]),
+            ('¢', [0b00100, 0b01111, 0b10100, 0b01111, 0b00100]),
+            ('£', [0b00110, 0b01001, 0b11100, 0b01000, 0b11111]),
+            ('¤', [0b10001, 0b01110, 0b01010, 0b01110, 0b10001]),
+            ('¥', [0b10001, 0b01010, 0b11111, 0b00100, 0b11111]),
+            ('¦', [0b00100, 0b00100, 0b00000, 0b00100, 0b00100]),
+            ('§', [0b01111, 0b11000, 0b01110, 0b00011, 0b11110]),
+            ('¨', [0b01010, 0b00000, 0b00000, 0b00000, 0b00000]),
+            ('©', [0b11111, 0b10011, 0b10111, 0b10011, 0b11111]),
+            ('ª', [0b01110, 0b10010, 0b01110, 0b00000, 0b01110]),
+            ('«', [0b00000, 0b01001, 0b10010, 0b01001, 0b00000]),
+            ('¬', [0b00000, 0b11111, 0b00001, 0b00000, 0b00000]),
+            ('®', [0b11111, 0b10011, 0b10011, 0b10101, 0b11111]),
+            ('¯', [0b11111, 0b00000, 0b00000, 0b00000, 0b00000]),
+            ('°', [0b00100, 0b01010, 0b00100, 0b00000, 0b00000]),
+            ('±', [0b00100, 0b01110, 0b00100, 0b00000, 0b01110]),
+            ('²', [0b01100, 0b00010, 0b00100, 0b01110, 0b00000]),
+            ('³', [0b01110, 0b00010, 0b00110, 0b00010, 0b01110]),
+            ('´', [0b00010, 0b00100, 0b00000, 0b00000, 0b00000]),
+            ('¶', [0b01111, 0b11101, 0b01101, 0b00101, 0b00101]),
+            ('·', [0b00000, 0b00000, 0b00100, 0b00000, 0b00000]),
+            ('¸', [0b00000, 0b00000, 0b00000, 0b00100, 0b01000]),
+            ('¹', [0b00100, 0b01100, 0b00100, 0b01110, 0b00000]),
+            ('º', [0b01100, 0b10010, 0b01100, 0b00000, 0b11110]),
+            ('»', [0b00000, 0b10010, 0b01001, 0b10010, 0b00000]),
+            ('¼', [0b10010, 0b10100, 0b01010, 0b10110, 0b00011]),
+            ('½', [0b10010, 0b10100, 0b01011, 0b10001, 0b00010]),
+            ('¾', [0b11010, 0b01100, 0b11010, 0b10110, 0b00011]),
+            ('¿', [0b00100, 0b00000, 0b01000, 0b10001, 0b01110]),
+            ('×', [0b00000, 0b01010, 0b00100, 0b01010, 0b00000]),
+            ('÷', [0b00100, 0b00000, 0b01110, 0b00000, 0b00100]),
+            ('€', [0b00111, 0b01000, 0b11110, 0b01000, 0b00111]),
+        ];
+
+        // Two rows above the letter and one below it
+        const GRAVE: [u8; 3] = [0b01000, 0b00100, 0];
+        const ACUTE: [u8; 3] = [0b00010, 0b00100, 0];
+        const CIRCUMFLEX: [u8; 3] = [0b00100, 0b01010, 0];
+        const TILDE: [u8; 3] = [0b01100, 0b10011, 0];
+        const DIAERESIS: [u8; 3] = [0b00000, 0b01010, 0];
+        const RING: [u8; 3] = [0b01110, 0b01010, 0];
+        const CEDILLA: [u8; 3] = [0, 0, 0b00100];
+
+        const ACCENTED: [(char, char, [u8; 3]); 27] = [
+            ('à', 'a', GRAVE),
+            ('á', 'a', ACUTE),
+            ('â', 'a', CIRCUMFLEX),
+            ('ã', 'a', TILDE),
+            ('ä', 'a', DIAERESIS),
+            ('å', 'a', RING),
+            ('ç', 'c', CEDILLA),
+            ('è', 'e', GRAVE),
+            ('é', 'e', ACUTE),
+            ('ê', 'e', CIRCUMFLEX),
+            ('ë', 'e', DIAERESIS),
+            ('ì', 'i', GRAVE),
+            ('í', 'i', ACUTE),
+            ('î', 'i', CIRCUMFLEX),
+            ('ï', 'i', DIAERESIS),
+            ('ñ', 'n', TILDE),
+            ('ò', 'o', GRAVE),
+            ('ó', 'o', ACUTE),
+            ('ô', 'o', CIRCUMFLEX),
+            ('õ', 'o', TILDE),
+            ('ö', 'o', DIAERESIS),
+            ('ù', 'u', GRAVE),
+            ('ú', 'u', ACUTE),
+            ('û', 'u', CIRCUMFLEX),
+            ('ü', 'u', DIAERESIS),
+            ('ý', 'y', ACUTE),
+            ('ÿ', 'y', DIAERESIS),
+        ];
+
+        let trans_str = "abcdefghijklmnopqrstuvwxyz0123456789!?:=,.-_() #'*/<>^|[]";
+
+        // Rows of every glyph, two for accents above the letter and one below it
+        let mut characters = trans_str
+            .chars()
+            .zip(CHARACTERS)
+            .chain(EXTRA_CHARACTERS)
+            .map(|(c, rows)| (c, [0, 0, rows[0], rows[1], rows[2], rows[3], rows[4], 0]))
+            .collect::<Vec<_>>();
+        for (c, base, accent) in ACCENTED {
+            let (_, rows) = characters.iter().find(|(b, _)| *b == base).unwrap();
+            let mut rows = *rows;
+            rows[0] = accent[0];
+            rows[1] = accent[1];
+            rows[7] = accent[2];
+            characters.push((c, rows));
+        }
+        let micro = CHARACTERS[CHARACTERS.len() - 1];
+        let micro = [0, 0, micro[0], micro[1], micro[2], micro[3], micro[4], 0];
+        characters.push(('\u{00b5}', micro));
+        characters.push(('μ', micro));
+
+        // Characters that look like another one
+        for (c, like) in [('\u{00a0}', ' '), ('\u{00ad}', '-')] {
+            let (_, rows) = characters.iter().find(|(b, _)| *b == like).unwrap();
+            characters.push((c, *rows));
+        }
+
+        // One glyph next to the other, the last one is a filled block for unknown characters
+        let mut sheet = Bitmap::new((characters.len() + 1) * 5, 8);
+        for (i, (_, rows)) in characters.iter().enumerate() {
+            for (y, row) in rows.iter().enumerate() {
                 for x in 0..5 {
-                    data[i][y][x] = ((character[y] >> (4 - x)) & 0x1) != 0;
+                    if ((row >> (4 - x)) & 0x1) != 0 {
+                        sheet.plot((i * 5 + x) as i32, y as i32, WHITE);
+                    }
                 }
             }
         }
+        sheet.draw_square(characters.len() as i32 * 5, 2, sheet.width as i32, 7, WHITE);
 
-        let trans_str = "abcdefghijklmnopqrstuvwxyz0123456789!?:=,.-_() #'*/<>^|[]";
+        let glyph = |i: usize| Glyph {
+            x: i as i32 * 5,
+            y: 0,
+            width: 5,
+            height: 8,
+            offset: ivec2(0, -2),
+            advance: 6,
+        };
+
+        let mut glyphs = characters
+            .iter()
+            .enumerate()
+            .map(|(i, (c, _))| (*c, glyph(i)))
+            .collect::<Vec<_>>();
+        glyphs.sort_by_key(|(c, _)| *c);
 
-        let mut translation = [255; 256];
-        translation[254] = CHARACTERS.len() - 1;
-        for (i, c) in trans_str.chars().enumerate() {
-            assert!(c.is_ascii());
-            let idx = (c as usize) & 0xff;
-            assert!(translation[idx] == 255);
-            translation[idx] = i;
+        Self {
+            sheet,
+            glyphs,
+            kerning: Vec::new(),
+            fallback: glyph(characters.len()),
+            line_height: 10,
         }
+    }
+
+    // Loads a BMFont text file, its single page is loaded from the same directory
+    pub fn load(path: &str) -> Self {
+        let text = std::fs::read_to_string(path)
+            .unwrap_or_else(|err| panic!("Failed to load font: \"{}\". Error: {}", path, err));
+        let directory = std::path::Path::new(path)
+            .parent()
+            .unwrap_or(std::path::Path::new(""));
+        Self::parse(&text, |page| {
+            Bitmap::load(directory.join(page).to_str().unwrap())
+        })
+        .unwrap_or_else(|err| panic!("Failed to parse font: \"{}\". Error: {}", path, err))
+    }
+
+    pub fn parse(text: &str, load_page: impl Fn(&str) -> Bitmap) -> Result<Self, String> {
+        let mut sheet = None;
+        let mut glyphs = Vec::new();
+        let mut kerning = Vec::new();
+        let mut line_height = None;
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let (tag, rest) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+            let values = parse_pairs(rest);
+            let char_value = |key| -> Result<char, String> {
+                char::from_u32(parse_value(&values, key, line_number)?)
+                    .ok_or_else(|| format!("line {}: invalid character", line_number))
+            };
+
+            match tag {
+                "common" => {
+                    line_height = Some(parse_value(&values, "lineHeight", line_number)?);
+                    if parse_value::<u32>(&values, "pages", line_number)? != 1 {
+                        return Err(format!(
+                            "line {}: only fonts with a single page are supported",
+                            line_number
+                        ));
+                    }
+                }
+                "page" => {
+                    let file = values
+                        .iter()
+                        .find(|(k, _)| *k == "file")
+                        .ok_or_else(|| format!("line {}: page without a file", line_number))?;
+                    sheet = Some(load_page(file.1));
+                }
+                "char" => glyphs.push((
+                    char_value("id")?,
+                    Glyph {
+                        x: parse_value(&values, "x", line_number)?,
+                        y: parse_value(&values, "y", line_number)?,
+                        width: parse_value(&values, "width", line_number)?,
+                        height: parse_value(&values, "height", line_number)?,
+                        offset: ivec2(
+                            parse_value(&values, "xoffset", line_number)?,
+                            parse_value(&values, "yoffset", line_number)?,
+                        ),
+                        advance: parse_value(&values, "xadvance", line_number)?,
+                    },
+                )),
+                "kerning" => kerning.push((
+                    (char_value("first")?, char_value("second")?),
+                    parse_value(&values, "amount", line_number)?,
+                )),
+                // info, chars and kernings only hold counts and settings for font tools
+                _ => {}
+            }
+        }
+
+        let mut sheet = sheet.ok_or("font has no page")?;
+        let line_height = line_height.ok_or("font has no common line")?;
+        glyphs.sort_by_key(|(c, _)| *c);
+        kerning.sort_by_key(|(pair, _)| *pair);
+
+        // Unknown characters show as a question mark, or a box added to the sheet when there
+        // is none
+        let fallback = match glyphs.iter().find(|(c, _)| *c == '?') {
+            Some((_, glyph)) => *glyph,
+            None => {
+                let size = ivec2(line_height / 2, line_height - 2).max(IVec2::ONE);
+                let mut extended = Bitmap::new(
+                    sheet.width + size.x as usize,
+                    sheet.height.max(size.y as usize),
+                );
+                sheet.draw_on(&mut extended, 0, 0);
+                let x = sheet.width as i32;
+                extended.draw_rectangle(x, 0, x + size.x - 1, size.y - 1, false, WHITE);
+                sheet = extended;
+                Glyph {
+                    x,
+                    y: 0,
+                    width: size.x,
+                    height: size.y,
+                    offset: IVec2::ZERO,
+                    advance: size.x + 1,
+                }
+            }
+        };
+
+        Ok(Self {
+            sheet,
+            glyphs,
+            kerning,
+            fallback,
+            line_height,
+        })
+    }
+
+    fn find(&self, c: char) -> Option<Glyph> {
+        self.glyphs
+            .binary_search_by_key(&c, |(c, _)| *c)
+            .ok()
+            .map(|i| self.glyphs[i].1)
+    }
+
+    // Fonts with a single case are used for both
+    pub fn glyph(&self, c: char) -> Glyph {
+        self.find(c)
+            .or_else(|| c.to_lowercase().next().and_then(|c| self.find(c)))
+            .or_else(|| c.to_uppercase().next().and_then(|c| self.find(c)))
+            .unwrap_or(self.fallback)
+    }
+
+    pub fn kerning(&self, first: char, second: char) -> i32 {
+        self.kerning
+            .binary_search_by_key(&(first, second), |(pair, _)| *pair)
+            .map_or(0, |i| self.kerning[i].1)
+    }
+
+    // Width of a single line, ignoring line breaks
+    pub fn measure_line(&self, text: &str) -> i32 {
+        let mut width = 0;
+        let mut previous = None;
+        for c in text.chars() {
+            if let Some(previous) = previous {
+                width += self.kerning(previous, c);
+            }
+            width += self.glyph(c).advance;
+            previous = Some(c);
+        }
+        width
+    }
+
+    // Breaks the text at line breaks, and between words to stay within `wrap_width`
+    pub fn layout(&self, text: &str, wrap_width: Option<i32>) -> Vec<TextLine> {
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        for paragraph in text.split('\n') {
+            let mut start = 0;
+            let mut end = 0; // end of the last word that fit
+            for (i, c) in paragraph.char_indices().chain([(paragraph.len(), ' ')]) {
+                // Words end at the first space after them
+                if c != ' ' || paragraph[..i].ends_with(' ') {
+                    continue;
+                }
+                let too_wide =
+                    wrap_width.is_some_and(|wrap| self.measure_line(&paragraph[start..i]) > wrap);
+                if too_wide && end > start {
+                    // A word that doesn't fit on its own line gets a line anyway
+                    lines.push(TextLine {
+                        start: offset + start,
+                        end: offset + end,
+                        width: self.measure_line(&paragraph[start..end]),
+                    });
+                    // The spaces at the break belong to neither line
+                    start = paragraph.len() - paragraph[end..].trim_start_matches(' ').len();
+                }
+                end = i;
+            }
+            lines.push(TextLine {
+                start: offset + start,
+                end: offset + paragraph.len(),
+                width: self.measure_line(&paragraph[start..]),
+            });
+            offset += paragraph.len() + 1;
+        }
+        lines
+    }
 
-        Self { data, translation }
+    // Size of the text when laid out with `layout`
+    pub fn measure(&self, text: &str, wrap_width: Option<i32>) -> IVec2 {
+        let lines = self.layout(text, wrap_width);
+        let width = lines.iter().map(|line| line.width).max().unwrap_or(0);
+        ivec2(width, lines.len() as i32 * self.line_height)
     }
 }
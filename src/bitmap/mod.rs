@@ -2,7 +2,7 @@
 
 pub mod font;
 pub use font::Font;
-use glam::IVec2;
+use glam::{Affine2, IVec2, Vec2, ivec2, vec2};
 pub use u32 as ColorChannel;
 
 #[derive(Debug, Clone)]
@@ -40,67 +40,116 @@ impl BitmapData {
     }
 }
 
-pub fn blend(a: u32, b: u32, alpha: u32) -> u32 {
-    let ar = (a >> 16) & 0xff;
-    let ag = (a >> 8) & 0xff;
-    let ab = a & 0xff;
-
-    let br = (b >> 16) & 0xff;
-    let bg = (b >> 8) & 0xff;
-    let bb = b & 0xff;
-
-    let red = ((ar * (255 - alpha)) + (br * alpha)) >> 8;
-    let green = ((ag * (255 - alpha)) + (bg * alpha)) >> 8;
-    let blue = ((ab * (255 - alpha)) + (bb * alpha)) >> 8;
-
-    let red = red.clamp(0, 255);
-    let green = green.clamp(0, 255);
-    let blue = blue.clamp(0, 255);
-    let alpha = 255;
-
-    (alpha << 24) | (red << 16) | (green << 8) | blue
+// Swaps the two skin colors of the player sprite for those of a mask, see `player_color_index`
+fn player_color(color: u32, color_index: u32) -> u32 {
+    const KEY_COLORS: (u32, u32) = (0xffdcb9, 0xe9be93);
+    const COLOR_TABLE: [(u32, u32); 5] = [
+        ((0xffdcb9 >> 2) & 0x3f3f3f, (0xe9be93 >> 2) & 0x3f3f3f), //start
+        (0xba1102, 0x681102),                                     // red
+        (0x096509, 0x224202),                                     // green
+        (0x2211B7, 0x221168),                                     // blue
+        (0xdcaf00, 0xb57c41),                                     // gold
+    ];
+    let color_set = COLOR_TABLE[color_index as usize];
+
+    let rgb = color & 0xffffff;
+    let rgb = if rgb == KEY_COLORS.0 {
+        color_set.0
+    } else if rgb == KEY_COLORS.1 {
+        color_set.1
+    } else {
+        rgb
+    };
+    (color & 0xff000000) | rgb
 }
 
-pub fn blend3(a: u32, b: u32, alpha: u32) -> u32 {
-    let ar = (a >> 16) & 0xff;
-    let ag = (a >> 8) & 0xff;
-    let ab = a & 0xff;
-
-    let alpha_r = (alpha >> 16) & 0xff;
-    let alpha_g = (alpha >> 8) & 0xff;
-    let alpha_b = alpha & 0xff;
+// How `draw_on_with` combines a source pixel with the target, sources are straight alpha
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlendMode {
+    Alpha,
+    Additive,
+    Multiply,
+    Screen,
+    Tint(u32), // multiplies the source by this ARGB color, including its alpha, then alpha blends
+    Light([u32; 3]), // scales the source RGB with 256 as 1.0, so it brightens too, then alpha blends
+}
 
-    let br = (b >> 16) & 0xff;
-    let bg = (b >> 8) & 0xff;
-    let bb = b & 0xff;
+#[inline]
+fn mul_255(a: u32, b: u32) -> u32 {
+    let x = a * b + 128;
+    (x + (x >> 8)) >> 8
+}
 
-    let red = ((ar * (256 - alpha_r)) + (br * alpha_r)) >> 8;
-    let green = ((ag * (256 - alpha_g)) + (bg * alpha_g)) >> 8;
-    let blue = ((ab * (256 - alpha_b)) + (bb * alpha_b)) >> 8;
+// Composites `src` over `dst` with the alpha of `src` premultiplied into its color
+pub fn composite(dst: u32, src: u32, mode: BlendMode) -> u32 {
+    let src = match mode {
+        BlendMode::Tint(tint) => {
+            let channel = |shift: u32| mul_255((src >> shift) & 0xff, (tint >> shift) & 0xff);
+            (channel(24) << 24) | (channel(16) << 16) | (channel(8) << 8) | channel(0)
+        }
+        BlendMode::Light([r, g, b]) => {
+            let channel =
+                |shift: u32, scale: u32| ((((src >> shift) & 0xff) * scale) >> 8).min(0xff);
+            (src & 0xff000000) | (channel(16, r) << 16) | (channel(8, g) << 8) | channel(0, b)
+        }
+        _ => src,
+    };
 
-    let red = red.clamp(0, 255);
-    let green = green.clamp(0, 255);
-    let blue = blue.clamp(0, 255);
-    let alpha = 255;
+    let sa = src >> 24;
+    if sa == 0 {
+        return dst;
+    }
+    let da = dst >> 24;
+    let inv = 255 - sa;
+
+    let channel = |shift: u32| {
+        let s = mul_255((src >> shift) & 0xff, sa);
+        let d = (dst >> shift) & 0xff;
+        match mode {
+            BlendMode::Alpha | BlendMode::Tint(_) | BlendMode::Light(_) => s + mul_255(d, inv),
+            BlendMode::Additive => (s + d).min(255),
+            BlendMode::Multiply => mul_255(s, d) + mul_255(d, inv),
+            BlendMode::Screen => s + d - mul_255(s, d),
+        }
+    };
 
-    (alpha << 24) | (red << 16) | (green << 8) | blue
+    let alpha = match mode {
+        BlendMode::Additive | BlendMode::Multiply | BlendMode::Screen => da,
+        _ => sa + mul_255(da, inv),
+    };
+    (alpha << 24) | (channel(16) << 16) | (channel(8) << 8) | channel(0)
 }
 
-pub fn add_blend(a: u32, b: u32) -> u32 {
-    let ar = (a >> 16) & 0xff;
-    let ag = (a >> 8) & 0xff;
-    let ab = a & 0xff;
-
-    let br = (b >> 16) & 0xff;
-    let bg = (b >> 8) & 0xff;
-    let bb = b & 0xff;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Center, // lines are centered on x
+    Right,  // lines end at x
+}
 
-    let red = (ar + br).min(255);
-    let green = (ag + bg).min(255);
-    let blue = (ab + bb).min(255);
-    let alpha = 255;
+#[derive(Debug, Clone, Copy)]
+pub struct TextStyle {
+    pub color: u32, // its alpha fades the whole text
+    pub shadow: Option<u32>,
+    pub outline: Option<u32>,
+    pub align: Align,
+    pub wrap_width: Option<i32>, // in pixels, lines break between words
+    pub scale: i32,
+    pub mode: BlendMode,
+}
 
-    (alpha << 24) | (red << 16) | (green << 8) | blue
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            color: WHITE,
+            shadow: None,
+            outline: None,
+            align: Align::Left,
+            wrap_width: None,
+            scale: 1,
+            mode: BlendMode::Alpha,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -254,6 +303,7 @@ impl Bitmap {
     }
 
     // With color masks
+    #[allow(clippy::too_many_arguments)]
     pub fn draw_on_scaled_colored_obj(
         &self,
         target: &mut Self,
@@ -294,6 +344,8 @@ impl Bitmap {
         let r_scale = ((brightness * rmask) >> 8).max(mute);
         let g_scale = ((brightness * gmask) >> 8).max(mute);
         let b_scale = ((brightness * bmask) >> 8).max(mute);
+        let light = BlendMode::Light([r_scale, g_scale, b_scale]);
+        let alpha = if is_colored { blend_mask } else { 0xff };
 
         let swf = self.width as f32 * scale_x;
         let shf = self.height as f32 * scale_y;
@@ -337,24 +389,9 @@ impl Bitmap {
                 unsafe {
                     let c: u32 = *srcline.add(((v >> 16) * self.width as i32 + (u >> 16)) as usize);
                     if (c & 0xff000000) != 0 {
-                        let r = (c >> 16) & 0xff;
-                        let g = (c >> 8) & 0xff;
-                        let b = c & 0xff;
-
-                        let r = ((r * r_scale) >> 8).min(0xff);
-                        let g = ((g * g_scale) >> 8).min(0xff);
-                        let b = ((b * b_scale) >> 8).min(0xff);
-
-                        let c = (r << 16) | (g << 8) | b;
-
-                        let c = if is_colored {
-                            let prev = *dstline.add(x as usize);
-                            blend(prev, c, blend_mask)
-                        } else {
-                            c
-                        };
-
-                        *dstline.add(x as usize) = c;
+                        let prev = *dstline.add(x as usize);
+                        *dstline.add(x as usize) =
+                            composite(prev, (alpha << 24) | (c & 0xffffff), light);
                     }
                 }
                 u += du;
@@ -377,18 +414,6 @@ impl Bitmap {
             return;
         }
 
-        let key_color0 = 0xffdcb9;
-        let key_color1 = 0xe9be93;
-
-        let color_table = [
-            ((0xffdcb9 >> 2) & 0x3f3f3f, (0xe9be93 >> 2) & 0x3f3f3f), //start
-            (0xba1102, 0x681102),                                     // red
-            (0x096509, 0x224202),                                     // green
-            (0x2211B7, 0x221168),                                     // blue
-            (0xdcaf00, 0xb57c41),                                     // gold
-        ];
-        let color_set = color_table[color_index as usize];
-
         let wf = self.width as f32;
         let hf = self.height as f32;
         let swf = wf * scale_x;
@@ -434,16 +459,7 @@ impl Bitmap {
                     let color: u32 =
                         *srcline.add(((v >> 16) * self.width as i32 + (u >> 16)) as usize);
                     if (color & 0xff000000) != 0 {
-                        let rgb = color & 0xffffff;
-                        let rgb = if rgb == key_color0 {
-                            color_set.0
-                        } else if rgb == key_color1 {
-                            color_set.1
-                        } else {
-                            rgb
-                        };
-
-                        *dstline.add(x as usize) = rgb;
+                        *dstline.add(x as usize) = player_color(color, color_index);
                     }
                 }
                 u += du;
@@ -453,6 +469,7 @@ impl Bitmap {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn draw_background(
         &self,
         target: &mut Self,
@@ -524,23 +541,16 @@ impl Bitmap {
                     let color: u32 =
                         *srcline.add(((v >> 16) * self.width as i32 + (u >> 16)) as usize);
                     if (color & 0xff000000) != 0 {
-                        let r = (color >> 16) & 0xff;
-                        let g = (color >> 8) & 0xff;
-                        let b = color & 0xff;
-
                         let mute = (low_brightness).max(mute);
 
                         let r_scale = ((brightness * rmask) >> 8).max(mute);
                         let g_scale = ((brightness * gmask) >> 8).max(mute);
                         let b_scale = ((brightness * bmask) >> 8).max(mute);
+                        let light = BlendMode::Light([r_scale, g_scale, b_scale]);
 
-                        let r = ((r * r_scale) >> 8).min(0xff);
-                        let g = ((g * g_scale) >> 8).min(0xff);
-                        let b = ((b * b_scale) >> 8).min(0xff);
-
-                        let color = (r << 16) | (g << 8) | b;
-
-                        *dstline.add(x as usize) = color;
+                        let prev = *dstline.add(x as usize);
+                        *dstline.add(x as usize) =
+                            composite(prev, 0xff000000 | (color & 0xffffff), light);
                     }
                 }
                 u += du;
@@ -551,6 +561,7 @@ impl Bitmap {
     }
 
     // With color masks
+    #[allow(clippy::too_many_arguments)]
     pub fn draw_tile(
         &self,
         target: &mut Self,
@@ -585,6 +596,8 @@ impl Bitmap {
         let r_scale = ((brightness * rmask) >> 8).max(mute);
         let g_scale = ((brightness * gmask) >> 8).max(mute);
         let b_scale = ((brightness * bmask) >> 8).max(mute);
+        let light = BlendMode::Light([r_scale, g_scale, b_scale]);
+        let alpha = if is_colored { blend_mask } else { 0xff };
 
         let mut sw = self.width as i32;
         let mut sh = self.height as i32;
@@ -612,27 +625,9 @@ impl Bitmap {
                 unsafe {
                     let c = *self.pixels().get_unchecked((line1 + sx + x) as usize);
                     if (c & 0xff000000) != 0 {
-                        let r = (c >> 16) & 0xff;
-                        let g = (c >> 8) & 0xff;
-                        let b = c & 0xff;
-
-                        let r = ((r * r_scale) >> 8).min(0xff);
-                        let g = ((g * g_scale) >> 8).min(0xff);
-                        let b = ((b * b_scale) >> 8).min(0xff);
-
-                        let c = (r << 16) | (g << 8) | b;
                         let index = (line0 + tx + x) as usize;
-
-                        let pixels = target.pixels_mut();
-
-                        let c = if is_colored {
-                            let prev = *pixels.get_unchecked_mut(index);
-                            blend(prev, c, blend_mask)
-                        } else {
-                            c
-                        };
-
-                        *pixels.get_unchecked_mut(index) = c;
+                        let pixel = target.pixels_mut().get_unchecked_mut(index);
+                        *pixel = composite(*pixel, (alpha << 24) | (c & 0xffffff), light);
                     }
                 }
             }
@@ -676,6 +671,108 @@ impl Bitmap {
         }
     }
 
+    // Like `draw_on` but uses the full alpha of the source, see `BlendMode`
+    pub fn draw_on_with(&self, target: &mut Self, x: i32, y: i32, mode: BlendMode) {
+        let mut sw = self.width as i32;
+        let mut sh = self.height as i32;
+
+        let (sx, tx) = if x < 0 {
+            sw += x;
+            (x.abs(), 0)
+        } else {
+            (0, x)
+        };
+        let (sy, ty) = if y < 0 {
+            sh += y;
+            (y.abs(), 0)
+        } else {
+            (0, y)
+        };
+
+        sw = sw.min(target.width as i32 - tx);
+        sh = sh.min(target.height as i32 - ty);
+
+        let target_stride = target.stride as i32;
+        let src = self.pixels();
+        let dst = target.pixels_mut();
+        for y in 0..sh {
+            let line0 = (ty + y) * target_stride;
+            let line1 = (sy + y) * (self.stride as i32);
+            for x in 0..sw {
+                unsafe {
+                    let c = *src.get_unchecked((line1 + sx + x) as usize);
+                    let d = dst.get_unchecked_mut((line0 + tx + x) as usize);
+                    *d = composite(*d, c, mode);
+                }
+            }
+        }
+    }
+
+    // Draws with `transform` mapping source pixels to target pixels, sampling the nearest pixel
+    pub fn draw_on_transformed(&self, target: &mut Self, transform: Affine2, mode: BlendMode) {
+        self.draw_on_transformed_with(target, transform, mode, |color| color);
+    }
+
+    // Like `draw_on_scaled_player`, with the colors of the player swapped while drawing
+    pub fn draw_on_transformed_player(
+        &self,
+        target: &mut Self,
+        transform: Affine2,
+        color_index: u32,
+    ) {
+        self.draw_on_transformed_with(target, transform, BlendMode::Alpha, |color| {
+            player_color(color, color_index)
+        });
+    }
+
+    fn draw_on_transformed_with(
+        &self,
+        target: &mut Self,
+        transform: Affine2,
+        mode: BlendMode,
+        recolor: impl Fn(u32) -> u32,
+    ) {
+        if transform.matrix2.determinant().abs() < 0.000001 {
+            return;
+        }
+
+        // Bounds of the transformed source, clipped to the target
+        let corners = [
+            vec2(0.0, 0.0),
+            vec2(self.width as f32, 0.0),
+            vec2(0.0, self.height as f32),
+            vec2(self.width as f32, self.height as f32),
+        ]
+        .map(|corner| transform.transform_point2(corner));
+        let min = corners.iter().fold(Vec2::MAX, |a, &b| a.min(b)).floor();
+        let max = corners.iter().fold(Vec2::MIN, |a, &b| a.max(b)).ceil();
+
+        let x0 = (min.x as i32).max(0);
+        let y0 = (min.y as i32).max(0);
+        let x1 = (max.x as i32).min(target.width as i32);
+        let y1 = (max.y as i32).min(target.height as i32);
+
+        let inverse = transform.inverse();
+        let du = inverse.matrix2.x_axis;
+
+        let target_stride = target.stride;
+        let src = self.pixels();
+        let dst = target.pixels_mut();
+        for y in y0..y1 {
+            let mut uv = inverse.transform_point2(vec2(x0 as f32 + 0.5, y as f32 + 0.5));
+            let line = y as usize * target_stride;
+            for x in x0..x1 {
+                let (u, v) = (uv.x.floor() as i32, uv.y.floor() as i32);
+                if u >= 0 && u < self.width as i32 && v >= 0 && v < self.height as i32 {
+                    let c = recolor(src[v as usize * self.stride + u as usize]);
+                    let d = &mut dst[line + x as usize];
+                    *d = composite(*d, c, mode);
+                }
+                uv += du;
+            }
+        }
+    }
+
     pub fn draw_square(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: u32) {
         let x0 = x0.clamp(0, self.width as i32 - 1);
         let x1 = x1.clamp(0, self.width as i32 - 1);
@@ -709,6 +806,14 @@ impl Bitmap {
         }
     }
 
+    pub fn blend_pixel(&mut self, x: i32, y: i32, color: u32, mode: BlendMode) {
+        if x >= 0 && x < self.width as i32 && y >= 0 && y < self.height as i32 {
+            let stride = self.stride;
+            let pixel = &mut self.pixels_mut()[x as usize + y as usize * stride];
+            *pixel = composite(*pixel, color, mode);
+        }
+    }
+
     pub fn load_pixel(&self, x: i32, y: i32) -> u32 {
         if x >= 0 && x < self.width as i32 && y >= 0 && y < self.height as i32 {
             let stride = self.stride;
@@ -851,28 +956,98 @@ impl Bitmap {
         }
     }
 
-    pub fn draw_str(&mut self, font: &Font, string: &str, mut x: i32, y: i32, color: u32) {
-        for c in string.chars() {
-            let ci = if c.is_ascii() {
-                (c.to_ascii_lowercase() as usize) & 0xff
-            } else if c == '\u{00b5}' || c == 'μ' {
-                254
-            } else {
-                255
-            };
-            let trans = font.translation[ci];
-            let character = &font.data[trans];
-
-            for (v, row) in character.iter().enumerate() {
-                for (u, &d) in row.iter().enumerate() {
-                    if d {
-                        self.plot(x + u as i32, y + v as i32, color);
-                        self.plot(x + 1 + u as i32, y + 1 + v as i32, 0);
-                    }
+    // Opaque text with a black drop shadow
+    pub fn draw_str(&mut self, font: &Font, string: &str, x: i32, y: i32, color: u32) {
+        let style = TextStyle {
+            color: color | 0xff000000,
+            shadow: Some(BLACK),
+            ..Default::default()
+        };
+        self.draw_text(font, string, x, y, &style);
+    }
+
+    // Draws multiple lines of text starting at `y`, see `TextStyle`
+    pub fn draw_text(&mut self, font: &Font, text: &str, x: i32, y: i32, style: &TextStyle) {
+        let scale = style.scale.max(1);
+        let lines = font.layout(text, style.wrap_width.map(|w| w / scale));
+
+        // Shadow and outline take the opacity of the text, for fading
+        let faded = |color: u32| {
+            let alpha = (color >> 24) * (style.color >> 24) / 255;
+            (alpha << 24) | (color & 0xffffff)
+        };
+
+        // Each layer is drawn for all of the text so it never covers the text itself
+        let mut layers = Vec::new();
+        if let Some(shadow) = style.shadow {
+            layers.push((faded(shadow), vec![ivec2(1, 1)]));
+        }
+        if let Some(outline) = style.outline {
+            let offsets = (-1..=1)
+                .flat_map(|y| (-1..=1).map(move |x| ivec2(x, y)))
+                .filter(|offset| *offset != IVec2::ZERO)
+                .collect();
+            layers.push((faded(outline), offsets));
+        }
+        layers.push((style.color, vec![IVec2::ZERO]));
+
+        for (color, offsets) in layers {
+            for offset in offsets {
+                for (i, line) in lines.iter().enumerate() {
+                    let line_x = match style.align {
+                        Align::Left => x,
+                        Align::Center => x - line.width * scale / 2,
+                        Align::Right => x - line.width * scale,
+                    };
+                    let pen = ivec2(line_x, y + i as i32 * font.line_height * scale);
+                    self.draw_text_line(
+                        font,
+                        &text[line.start..line.end],
+                        pen + offset * scale,
+                        scale,
+                        color,
+                        style.mode,
+                    );
                 }
             }
+        }
+    }
 
-            x += 6;
+    fn draw_text_line(
+        &mut self,
+        font: &Font,
+        line: &str,
+        mut pen: IVec2,
+        scale: i32,
+        color: u32,
+        mode: BlendMode,
+    ) {
+        let mut previous = None;
+        for c in line.chars() {
+            if let Some(previous) = previous {
+                pen.x += font.kerning(previous, c) * scale;
+            }
+            previous = Some(c);
+
+            let glyph = font.glyph(c);
+            for v in 0..glyph.height {
+                for u in 0..glyph.width {
+                    let coverage = font.sheet.load_pixel(glyph.x + u, glyph.y + v) >> 24;
+                    if coverage == 0 {
+                        continue;
+                    }
+                    let alpha = (color >> 24) * coverage / 255;
+                    let color = (alpha << 24) | (color & 0xffffff);
+
+                    let p = pen + (glyph.offset + ivec2(u, v)) * scale;
+                    for y in 0..scale {
+                        for x in 0..scale {
+                            self.blend_pixel(p.x + x, p.y + y, color, mode);
+                        }
+                    }
+                }
+            }
+            pen.x += glyph.advance * scale;
         }
     }
 }
@@ -0,0 +1,55 @@
+// User settings stored as `key = value` lines, missing keys fall back to the defaults
+use std::fmt::Display;
+use std::str::FromStr;
+
+pub const CONFIG_PATH: &str = "config.txt";
+
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    entries: Vec<(String, String)>,
+}
+
+impl Config {
+    // A missing file is an empty config
+    pub fn from_file(path: &str) -> Self {
+        let Ok(text) = std::fs::read_to_string(path) else {
+            return Self::default();
+        };
+
+        let entries = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim().to_owned(), value.trim().to_owned()))
+            .collect();
+
+        Self { entries }
+    }
+
+    pub fn store_to_file(&self, path: &str) {
+        let text = self
+            .entries
+            .iter()
+            .map(|(key, value)| format!("{} = {}\n", key, value))
+            .collect::<String>();
+        if let Err(err) = std::fs::write(path, text) {
+            println!("Failed to store config: \"{}\". Error: {}", path, err);
+        }
+    }
+
+    pub fn get<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, value)| value.parse().ok())
+    }
+
+    pub fn set(&mut self, key: &str, value: impl Display) {
+        let value = value.to_string();
+        if let Some(entry) = self.entries.iter_mut().find(|(k, _)| k == key) {
+            entry.1 = value;
+        } else {
+            self.entries.push((key.to_owned(), value));
+        }
+    }
+}
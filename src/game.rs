@@ -1,23 +1,42 @@
+pub mod animator;
+pub mod atlas;
 pub mod background;
 pub mod camera;
 pub mod editor;
 pub mod enemy;
+pub mod inspector;
+pub mod jam;
+pub mod overlays;
+pub mod palette;
+pub mod particles;
+pub mod reachability;
+pub mod selection;
+pub mod settings;
 pub mod sprite;
 pub mod tilemap;
+pub mod ui;
 
 use std::rc::Rc;
 
 use crate::audio::Audio;
-use crate::audio::sound::SoundTypes;
-use crate::bitmap::{self, Bitmap, Font};
+use crate::audio::clock::Quantize;
+use crate::audio::layers::MusicState;
+use crate::bitmap::{self, Align, Bitmap, BlendMode, Font, TextStyle};
+use crate::game::animator::Animator;
+use crate::game::atlas::Atlas;
 use crate::game::background::Background;
-use crate::game::camera::{Camera, world_space_to_screen_space};
-use crate::game::sprite::Sprite;
+use crate::game::camera::{Camera, screen_to_world_space, world_space_to_screen_space};
+use crate::game::sprite::{Sprite, sprite_transform};
+use crate::strings::Strings;
 use editor::{EditorState, ObjectType};
 use enemy::{Enemy, spawn_enemies};
 use glam::*;
+use jam::Jam;
+use particles::Particles;
+use reachability::Spawn;
 
 use tilemap::{TileFlags, TileMap, TileSet};
+use ui::{Ui, UiState};
 
 const GRAVITY: f32 = 600.0;
 const JUMP_IMPULSE: f32 = 150.0;
@@ -34,7 +53,40 @@ const ALLOW_EDITOR: bool = true;
 const ALLOW_KEYBOAD_MODE: bool = true;
 const ENABLE_AUDIO: bool = true;
 
-#[derive(Debug)]
+// Enemies closer than this make the music tense
+const DANGER_DISTANCE: f32 = 64.0;
+
+const DEATH_SEQUENCE_DURATION: f32 = 1.5;
+const DEATH_SPIN_SPEED: f32 = 8.0; // radians per second
+
+const MASK_ROCK_SPEED: f32 = 3.0;
+const MASK_ROCK_ANGLE: f32 = 0.3;
+const WINNING_SEQUENCE_DURATION: f32 = 5.0;
+
+// Seconds the death, win and key hint texts take to fade in
+const TEXT_FADE_IN: f32 = 0.5;
+
+// Seconds the language name is shown after switching languages
+
+// Opacity of the glow around the player in the color of the active mask
+const PLAYER_GLOW_ALPHA: u32 = 0x50;
+
+// Big centered text for winning and dying
+const TITLE_STYLE: TextStyle = TextStyle {
+    color: bitmap::WHITE,
+    shadow: None,
+    outline: None,
+    align: Align::Center,
+    wrap_width: None,
+    scale: 2,
+    mode: BlendMode::Alpha,
+};
+
+fn fade_in(seconds: f32) -> u32 {
+    (((seconds / TEXT_FADE_IN).clamp(0.0, 1.0) * 255.0) as u32) << 24
+}
+
+#[derive(Debug, Clone)]
 pub struct SaveState {
     pub player_position: Vec2,
     pub has_red_mask: bool,
@@ -71,6 +123,7 @@ pub enum Key {
 
     Key1,
     Key2,
+    Key3,
 
     M,
     MusicC3,
@@ -90,6 +143,13 @@ pub enum Key {
     MusicD4,
     MusicDs4,
     MusicE4,
+    MusicOctaveDown,
+    MusicOctaveUp,
+    MusicWave,
+    MusicSustain,
+    MusicRecord,
+    MusicClear,
+    MusicExport,
 
     MaskRed,
     MaskGreen,
@@ -98,6 +158,22 @@ pub enum Key {
 
     EditorZoomIn,
     EditorZoomOut,
+    EditorFilterCollision,
+    EditorFilterSpike,
+    EditorFavourites,
+    EditorFavourite,
+    EditorCopy,
+    EditorCut,
+    EditorPaste,
+    EditorDelete,
+    EditorFlipX,
+    EditorFlipY,
+    EditorRotate,
+    EditorPlay,
+
+    Settings,
+
+    Backspace,
 
     Count,
 }
@@ -161,16 +237,6 @@ fn draw_aabb_ws(screen: &mut Bitmap, aabb: &Aabb, camera: &Camera, color: u32) {
         color,
     );
 }
-fn draw_aabb_ss(screen: &mut Bitmap, aabb: &Aabb, color: u32) {
-    screen.draw_rectangle(
-        aabb.min.x as i32,
-        aabb.min.y as i32,
-        aabb.max.x as i32,
-        aabb.max.y as i32,
-        false,
-        color,
-    );
-}
 
 #[derive(Debug, Clone)]
 struct MaskObject {
@@ -199,18 +265,24 @@ struct SaveGamePoint {
     sprite_save_off: Rc<Bitmap>,
     sprite_save_on: Rc<Bitmap>,
     activated: bool,
+    chime_timer: f32,
 }
 impl SaveGamePoint {
+    // Savepoints that are not activated yet chime so they can be found by ear
+    const CHIME_INTERVAL: f32 = 4.0;
+    const AABB: Aabb = Aabb {
+        min: Vec2::ZERO,
+        max: vec2(7.0, 7.0),
+    };
+
     fn new(position: Vec2, sprite_save_off: Rc<Bitmap>, sprite_save_on: Rc<Bitmap>) -> Self {
         Self {
             position,
             sprite_save_off,
             sprite_save_on,
-            aabb: Aabb {
-                min: Vec2::ZERO,
-                max: vec2(7.0, 7.0),
-            },
+            aabb: Self::AABB,
             activated: false,
+            chime_timer: Self::CHIME_INTERVAL,
         }
     }
 
@@ -222,13 +294,20 @@ impl SaveGamePoint {
     }
 }
 
-#[derive(Debug)]
+// The game from before a play-test started in the editor, put back when returning to it
+struct Playtest {
+    player: Player,
+    save_state: Option<SaveState>,
+    inventory: Vec<MaskObject>,
+    masks_visible: Vec<bool>,
+    color_mask: u32,
+    enemies: Vec<Enemy>,
+    savepoints: Vec<SaveGamePoint>,
+}
+
+#[derive(Debug, Clone)]
 struct Player {
-    idle_sprite: Sprite,
-    walk_sprite: Sprite,
-    jump_sprite: Sprite,
-    death_sprite: Sprite,
-    win_sprite: Sprite,
+    animator: Animator,
 
     position: Vec2,
     velocity: Vec2,
@@ -237,6 +316,7 @@ struct Player {
     is_jumping: bool,
     is_dead: bool,
     is_winner: bool,
+    death_time: f32,
 }
 
 impl Player {
@@ -247,42 +327,37 @@ impl Player {
         }
     }
 
-    fn tick(&mut self, delta_time: f32) {
-        self.walk_sprite.tick(delta_time);
-
-        if !self.on_ground {
-            self.jump_sprite.tick(delta_time);
-        } else {
-            self.jump_sprite.t = 0.0;
-        }
-
+    // Returns the animation events, see assets/sprite/spritesheet_animation.txt
+    fn tick(&mut self, delta_time: f32) -> Vec<String> {
         if self.is_dead {
-            self.death_sprite.tick(delta_time);
-        }
-        if self.is_winner {
-            self.win_sprite.tick(delta_time);
+            self.death_time += delta_time;
         }
+
+        self.animator.set_bool("winner", self.is_winner);
+        self.animator.set_bool("dead", self.is_dead);
+        self.animator.set_bool("ground", self.on_ground);
+        self.animator.set("speed", self.velocity.x.abs());
+        self.animator.tick(delta_time)
     }
 
     fn draw(&self, screen: &mut Bitmap, camera: &Camera, color_mask: u32) {
         let scale = vec2(if self.velocity.x < 0.0 { -1.0 } else { 1.0 }, 1.0) * camera.zoom;
         let screen_pos = world_space_to_screen_space(self.position, camera);
 
-        if self.is_winner {
-            self.win_sprite
-                .draw_player(screen, screen_pos, scale, color_mask);
-        } else if self.is_dead {
-            self.death_sprite
-                .draw_player(screen, screen_pos, scale, color_mask);
-        } else if !self.on_ground {
-            self.jump_sprite
-                .draw_player(screen, screen_pos, scale, color_mask);
-        } else if self.velocity.x.abs() < 0.001 {
-            self.idle_sprite
-                .draw_player(screen, screen_pos, scale, color_mask);
+        let sprite = self.animator.sprite();
+        if self.is_dead && !self.is_winner {
+            // Tumble around the center of the frame while falling off the screen
+            let pivot = sprite.frame().pivot;
+            let transform = Mat2::from_scale_angle(scale, self.death_time * DEATH_SPIN_SPEED);
+            sprite.draw_player_transformed(
+                screen,
+                screen_pos + pivot * camera.zoom,
+                pivot,
+                transform,
+                color_mask,
+            );
         } else {
-            self.walk_sprite
-                .draw_player(screen, screen_pos, scale, color_mask);
+            sprite.draw_player(screen, screen_pos, scale, color_mask);
         }
     }
 }
@@ -292,6 +367,7 @@ pub struct InputState {
     pub mouse: Vec2,
     pub mouse_delta: Vec2,
     mouse_scroll_delta: Vec2,
+    text_input: String, // characters typed this frame
 
     pub axis_state: [f32; Axis::Count as usize],
 
@@ -309,6 +385,7 @@ impl Default for InputState {
             mouse: Vec2::default(),
             mouse_delta: Vec2::default(),
             mouse_scroll_delta: Vec2::default(),
+            text_input: String::new(),
 
             axis_state: [0.0; Axis::Count as usize],
 
@@ -353,6 +430,7 @@ impl InputState {
     fn reset(&mut self) {
         self.mouse_delta = Vec2::ZERO;
         self.mouse_scroll_delta = Vec2::ZERO;
+        self.text_input.clear();
 
         self.key_pressed.fill(false);
         self.key_released.fill(false);
@@ -366,8 +444,14 @@ pub struct Game {
     pub reset_game_bool_hack: bool,
     audio: Option<Audio>,
     music_mode: bool,
+    jam: Jam,
 
     font: Font,
+    title_font: Font,
+    strings: Strings,
+    settings_open: bool,
+    settings_ui: UiState,
+    language_first: usize, // top row of the language list in the settings
 
     tile_set: TileSet,
     tile_map: TileMap,
@@ -380,6 +464,8 @@ pub struct Game {
     editor_state: EditorState,
 
     background: Background,
+    particles: Particles,
+    player_glow: Bitmap,
 
     save_state: Option<SaveState>,
 
@@ -398,8 +484,6 @@ pub struct Game {
     died_position: Vec2,
     player: Player,
     player_inventory: PlayerInventory,
-    is_player_walking: bool,
-    was_player_walking: bool,
     time: f32,
 
     player_uses_controller: bool,
@@ -414,6 +498,7 @@ pub struct Game {
     winning_sequence_duration: f32,
 
     editor_mode: bool,
+    playtest: Option<Playtest>,
 
     color_mask: crate::bitmap::ColorChannel,
     lerp_color_mask: Vec3,
@@ -561,6 +646,19 @@ impl Game {
         let mut aura2 = Bitmap::new(256, 256);
         aura_low.draw_on_scaled(&mut aura2, 0, 0, 16.0, 16.0);
 
+        let mut player_glow = Bitmap::new(32, 32);
+        for y in 0..player_glow.height {
+            for x in 0..player_glow.width {
+                let p = vec2(x as f32, y as f32) / 16.0 - 1.0;
+                let alpha = (1.0 - p.length_squared()).clamp(0.0, 1.0).powi(2);
+                player_glow.plot(
+                    x as i32,
+                    y as i32,
+                    ((alpha * 255.0) as u32) << 24 | 0xffffff,
+                );
+            }
+        }
+
         let tile_set = TileSet {
             tiles,
             tile_types,
@@ -656,110 +754,30 @@ impl Game {
             sprite_save_on.clone(),
         );
 
-        let player_sprite_sheet = Bitmap::load("assets/sprite/spritesheet_animation.png");
+        let player_atlas = Atlas::load("assets/sprite/spritesheet_animation.png");
+        let player_aabb = player_atlas.sprite("idle").frame().hitbox.clone();
 
-        let walk_frames = [
-            (0, 16),
-            (16, 16),
-            (32, 16),
-            (48, 16),
-            (64, 16),
-            (80, 16),
-            (96, 16),
-            (112, 16),
-        ];
-        let jump_frames = [(0, 80), (16, 80)];
-        let win_frames = [
-            (0, 100),
-            (16, 100),
-            (32, 100),
-            (48, 100),
-            (64, 100),
-            (80, 100),
-        ];
-
-        let idle_sprite = Sprite {
-            frames: build_frame_list(&player_sprite_sheet, &[(0, 0)], (16, 16)),
-            frame_index: 0,
-            t: 0.0,
-            seconds_per_frame: 1.0 / 24.0,
-        };
-        let walk_sprite = Sprite {
-            frames: build_frame_list(&player_sprite_sheet, &walk_frames, (16, 16)),
-            frame_index: 0,
-            t: 0.0,
-            seconds_per_frame: 1.0 / 24.0,
-        };
-        let jump_sprite = Sprite {
-            frames: build_frame_list(&player_sprite_sheet, &jump_frames, (16, 16)),
-            frame_index: 0,
-            t: 0.0,
-            seconds_per_frame: 1.0 / 4.0,
-        };
-        let death_sprite = Sprite {
-            frames: build_frame_list(&player_sprite_sheet, &[(0, 32)], (16, 16)), // TODO: Add anim
-            frame_index: 0,
-            t: 0.0,
-            seconds_per_frame: 1.0 / 4.0,
-        };
-        let win_sprite = Sprite {
-            frames: build_frame_list(&player_sprite_sheet, &win_frames, (16, 16)), // TODO: Own frames
-            frame_index: 0,
-            t: 0.0,
-            seconds_per_frame: 1.0 / 12.0,
-        };
-
-        let enemy_sprite_sheet = Bitmap::load("assets/sprite/enemy_sprite.png");
-        let enemy_sprite_white = Sprite {
-            frames: build_frame_list(
-                &enemy_sprite_sheet,
-                &(0..8).map(|i| (i * 16, 0)).collect::<Vec<_>>(),
-                (16, 8),
-            ),
-            frame_index: 0,
-            t: 0.0,
-            seconds_per_frame: 1.0 / 24.0,
-        };
-        let enemy_sprite_red = Sprite {
-            frames: build_frame_list(
-                &enemy_sprite_sheet,
-                &(0..8).map(|i| (i * 16, 8)).collect::<Vec<_>>(),
-                (16, 8),
-            ),
-            frame_index: 0,
-            t: 0.0,
-            seconds_per_frame: 1.0 / 24.0,
-        };
-        let enemy_sprite_green = Sprite {
-            frames: build_frame_list(
-                &enemy_sprite_sheet,
-                &(0..8).map(|i| (i * 16, 16)).collect::<Vec<_>>(),
-                (16, 8),
-            ),
-            frame_index: 0,
-            t: 0.0,
-            seconds_per_frame: 1.0 / 24.0,
-        };
-        let enemy_sprite_blue = Sprite {
-            frames: build_frame_list(
-                &enemy_sprite_sheet,
-                &(0..8).map(|i| (i * 16, 24)).collect::<Vec<_>>(),
-                (16, 8),
-            ),
-            frame_index: 0,
-            t: 0.0,
-            seconds_per_frame: 1.0 / 24.0,
-        };
+        let enemy_atlas = Atlas::load("assets/sprite/enemy_sprite.png");
+        let enemy_sprite_white = enemy_atlas.sprite("white");
+        let enemy_sprite_red = enemy_atlas.sprite("red");
+        let enemy_sprite_green = enemy_atlas.sprite("green");
+        let enemy_sprite_blue = enemy_atlas.sprite("blue");
 
         let mut game = Self {
             reset_game_bool_hack: false,
             audio: if ENABLE_AUDIO {
-                Some(Audio::new())
+                Some(Audio::new("level0"))
             } else {
                 None
             },
             music_mode: false,
+            jam: Jam::new(),
             font: Font::new_default(),
+            title_font: Font::load("assets/fonts/pixl.fnt"),
+            strings: Strings::new(),
+            settings_open: false,
+            settings_ui: UiState::default(),
+            language_first: 0,
 
             actual_camera: vec2(2000.0, 2000.0),
             camera: Camera {
@@ -771,9 +789,21 @@ impl Game {
 
             save_state: None,
 
-            editor_state: EditorState::new(&enemy_sprite_sheet, save_point_icon),
+            editor_state: EditorState::new(
+                &enemy_atlas,
+                save_point_icon,
+                Spawn {
+                    position: Self::PLAYER_START_POS,
+                    hitbox: player_aabb.clone(),
+                    pickups: [&red_mask, &green_mask, &blue_mask, &golden_mask]
+                        .map(|mask| (mask.color, mask.aabb_world_space()))
+                        .to_vec(),
+                },
+            ),
 
             background: Background::new(),
+            particles: Particles::new(),
+            player_glow,
             tile_set,
             tile_map,
 
@@ -792,29 +822,21 @@ impl Game {
 
             died_position: Vec2::ZERO,
             player: Player {
-                idle_sprite,
-                walk_sprite,
-                jump_sprite,
-                death_sprite,
-                win_sprite,
+                animator: player_atlas.animator(),
                 position: Self::PLAYER_START_POS,
                 velocity: Vec2::ZERO,
-                aabb: Aabb {
-                    min: vec2(3.0, 5.0),
-                    max: vec2(12.0, 15.0),
-                },
+                aabb: player_aabb,
                 on_ground: false,
                 is_jumping: false,
                 is_dead: false,
                 is_winner: false,
+                death_time: 0.0,
             },
             player_inventory: PlayerInventory {
                 tile_size: 16,
                 position_on_screen: vec2(0.0, 180.0),
                 masks: Vec::new(),
             },
-            is_player_walking: false,
-            was_player_walking: false,
             time: 0.0,
 
             jump_key_hint_delay: 4.0,
@@ -822,14 +844,15 @@ impl Game {
 
             player_uses_controller: true,
 
-            death_sequence_duration: 1.5,
+            death_sequence_duration: DEATH_SEQUENCE_DURATION,
             death_sequence_is_playing: false,
 
-            winning_sequence_duration: 5.0,
+            winning_sequence_duration: WINNING_SEQUENCE_DURATION,
             winning_sequence_is_playing: false,
 
             color_mask: Self::START_COLOR_MASK,
             editor_mode: false,
+            playtest: None,
             lerp_color_mask: Vec3::ZERO,
         };
 
@@ -844,13 +867,20 @@ impl Game {
         self.player.is_jumping = false;
         self.player.is_dead = false;
         self.player.is_winner = false;
+        self.player.death_time = 0.0;
+        self.player.animator.play("idle");
 
-        self.enemies = spawn_enemies(
-            &self.enemy_sprite_white,
-            &self.enemy_sprite_red,
-            &self.enemy_sprite_green,
-            &self.enemy_sprite_blue,
-        );
+        // Play-tests use the objects placed in the editor
+        if self.playtest.is_some() {
+            self.spawn_objects();
+        } else {
+            self.enemies = spawn_enemies(
+                &self.enemy_sprite_white,
+                &self.enemy_sprite_red,
+                &self.enemy_sprite_green,
+                &self.enemy_sprite_blue,
+            );
+        }
 
         // Reset inventory
         self.player_inventory.masks.clear();
@@ -861,13 +891,19 @@ impl Game {
         }
         self.color_mask = Self::START_COLOR_MASK;
 
+        self.particles.clear();
+
         // Reset death sequence
-        self.death_sequence_duration = 1.5;
+        self.death_sequence_duration = DEATH_SEQUENCE_DURATION;
         self.death_sequence_is_playing = false;
 
-        self.winning_sequence_duration = 5.0;
+        self.winning_sequence_duration = WINNING_SEQUENCE_DURATION;
         self.winning_sequence_is_playing = false;
 
+        if let Some(audio) = &self.audio {
+            audio.switch_song("level0", Quantize::Bar);
+        }
+
         self.restore_save_game();
     }
 
@@ -902,6 +938,89 @@ impl Game {
         }
     }
 
+    // Enemies and savepoints from the objects placed in the editor
+    fn spawn_objects(&mut self) {
+        self.enemies.clear();
+        self.savepoint_objects.clear();
+
+        for spawn in self.editor_state.object_spawns.iter() {
+            let (sprite, color) = match spawn.object_type {
+                ObjectType::WhiteHedgehog => (&self.enemy_sprite_white, 0xffffff),
+                ObjectType::RedHedgehog => (&self.enemy_sprite_red, bitmap::RED),
+                ObjectType::GreenHedgehog => (&self.enemy_sprite_green, bitmap::GREEN),
+                ObjectType::BlueHedgehog => (&self.enemy_sprite_blue, bitmap::BLUE),
+                ObjectType::Savepoint => {
+                    self.savepoint_objects.push(SaveGamePoint::new(
+                        spawn.position,
+                        self.sprite_save_off.clone(),
+                        self.sprite_save_on.clone(),
+                    ));
+                    continue;
+                }
+            };
+
+            let properties = &spawn.properties;
+            let mut enemy = Enemy::new(spawn.position, properties.going_left, sprite, color);
+            enemy.speed = properties.speed;
+            enemy.patrol = properties
+                .patrol
+                .map(|(left, right)| (spawn.position.x - left, spawn.position.x + right));
+            self.enemies.push(enemy);
+        }
+    }
+
+    // Leaves the editor and plays from `start`, dying also restarts there
+    fn start_playtest(&mut self, mut start: SaveState) {
+        self.playtest = Some(Playtest {
+            player: self.player.clone(),
+            save_state: self.save_state.take(),
+            inventory: std::mem::take(&mut self.player_inventory.masks),
+            masks_visible: self
+                .mask_game_objects
+                .iter()
+                .map(|mask| mask.visible)
+                .collect(),
+            color_mask: self.color_mask,
+            enemies: std::mem::take(&mut self.enemies),
+            savepoints: std::mem::take(&mut self.savepoint_objects),
+        });
+
+        // The player is centered on the picked position
+        start.player_position -= self.player.aabb.center();
+        self.save_state = Some(start);
+
+        self.editor_mode = false;
+        self.camera.zoom = 1.0;
+        self.actual_camera = self.camera.position;
+        self.reset_game();
+    }
+
+    fn end_playtest(&mut self) {
+        let Some(playtest) = self.playtest.take() else {
+            return;
+        };
+
+        self.player = playtest.player;
+        self.save_state = playtest.save_state;
+        self.player_inventory.masks = playtest.inventory;
+        for (mask, visible) in self
+            .mask_game_objects
+            .iter_mut()
+            .zip(playtest.masks_visible)
+        {
+            mask.visible = visible;
+        }
+        self.color_mask = playtest.color_mask;
+        self.enemies = playtest.enemies;
+        self.savepoint_objects = playtest.savepoints;
+
+        self.particles.clear();
+        self.death_sequence_duration = DEATH_SEQUENCE_DURATION;
+        self.death_sequence_is_playing = false;
+        self.winning_sequence_duration = WINNING_SEQUENCE_DURATION;
+        self.winning_sequence_is_playing = false;
+    }
+
     pub(crate) fn on_mouse_moved(&mut self, x: f32, y: f32) {
         let new_mouse_pos = vec2(x, y);
         self.input_state.mouse_delta = new_mouse_pos - self.input_state.mouse;
@@ -918,71 +1037,53 @@ impl Game {
         self.input_state.mouse_state[button as usize] = false;
         self.input_state.mouse_released[button as usize] = true;
     }
+    pub(crate) fn on_char_typed(&mut self, c: char) {
+        if !c.is_control() {
+            self.input_state.text_input.push(c);
+        }
+    }
     pub(crate) fn on_key_down(&mut self, key: Key) {
+        // P plays a note in music mode instead of play-testing from the mouse
+        if self.music_mode && key == Key::EditorPlay {
+            return;
+        }
+
         self.input_state.key_state[key as usize] = true;
         self.input_state.key_pressed[key as usize] = true;
 
+        // Keys typed into a text field of the editor aren't shortcuts
+        if self.editor_mode && self.editor_state.is_typing() {
+            return;
+        }
+
         if self.music_mode
             && let Some(audio) = &self.audio
         {
-            audio.key_sender.send((key, true)).unwrap();
+            self.jam.on_key(key, true, audio);
         }
 
         match key {
             Key::Space => {
                 self.editor_mode = (!self.editor_mode) && ALLOW_EDITOR;
-                if !self.editor_mode {
+                if self.editor_mode {
+                    self.settings_open = false;
+                    self.end_playtest();
+                } else {
                     self.camera.zoom = 1.0;
-
-                    self.enemies.clear();
-                    self.savepoint_objects.clear();
-
-                    for spawn in self.editor_state.object_spawns.iter() {
-                        match spawn.object_type {
-                            ObjectType::WhiteHedgehog => {
-                                self.enemies.push(Enemy::new(
-                                    spawn.position,
-                                    false,
-                                    &self.enemy_sprite_white,
-                                    0xffffff,
-                                ));
-                            }
-                            ObjectType::RedHedgehog => {
-                                self.enemies.push(Enemy::new(
-                                    spawn.position,
-                                    false,
-                                    &self.enemy_sprite_red,
-                                    bitmap::RED,
-                                ));
-                            }
-                            ObjectType::GreenHedgehog => {
-                                self.enemies.push(Enemy::new(
-                                    spawn.position,
-                                    false,
-                                    &self.enemy_sprite_green,
-                                    bitmap::GREEN,
-                                ));
-                            }
-                            ObjectType::BlueHedgehog => {
-                                self.enemies.push(Enemy::new(
-                                    spawn.position,
-                                    false,
-                                    &self.enemy_sprite_blue,
-                                    bitmap::BLUE,
-                                ));
-                            }
-                            ObjectType::Savepoint => {
-                                self.savepoint_objects.push(SaveGamePoint::new(
-                                    spawn.position,
-                                    self.sprite_save_off.clone(),
-                                    self.sprite_save_on.clone(),
-                                ));
-                            }
-                        }
-                    }
+                    self.spawn_objects();
+                }
+            }
+            Key::Settings => {
+                self.settings_open = !self.settings_open && !self.editor_mode;
+            }
+            Key::M => {
+                self.music_mode = (!self.music_mode) && ALLOW_KEYBOAD_MODE;
+                if !self.music_mode
+                    && let Some(audio) = &self.audio
+                {
+                    self.jam.stop(audio);
                 }
             }
-            Key::M => self.music_mode = (!self.music_mode) && ALLOW_KEYBOAD_MODE,
             _ => {}
         }
     }
@@ -993,7 +1094,7 @@ impl Game {
         if self.music_mode
             && let Some(audio) = &self.audio
         {
-            audio.key_sender.send((key, false)).unwrap();
+            self.jam.on_key(key, false, audio);
         }
     }
 
@@ -1043,10 +1144,70 @@ impl Game {
         }
     }
 
+    // What the adaptive music reacts to, see the layer rules in assets/music
+    fn music_state(&self) -> MusicState {
+        let player_center = self.player.aabb_world_space().center();
+        let closest_enemy = self
+            .enemies
+            .iter()
+            .filter(|enemy| {
+                !enemy.is_colored() || (self.color_mask & enemy.color_mask) & 0xffffff != 0
+            })
+            .map(|enemy| {
+                enemy
+                    .hitbox_aabb_world_space()
+                    .center()
+                    .distance(player_center)
+            })
+            .fold(f32::INFINITY, f32::min);
+
+        MusicState {
+            masks_owned: [bitmap::RED, bitmap::GREEN, bitmap::BLUE]
+                .map(|color| self.player_inventory.masks.iter().any(|m| m.color == color)),
+            active_color: [16, 8, 0].map(|shift| (self.color_mask >> shift) & 0xff != 0),
+            danger: (1.0 - closest_enemy / DANGER_DISTANCE).max(0.0),
+            dead: self.player.is_dead,
+        }
+    }
+
     pub fn tick(&mut self, delta_time: f32, screen: &mut Bitmap) {
         let delta_time = delta_time.min(1.0 / 30.0);
+
+        // The game stands still while the settings are open
+        let game_delta_time = if self.settings_open { 0.0 } else { delta_time };
+        self.tick_game(game_delta_time, screen);
+
+        if self.settings_open {
+            let mut ui = Ui::begin(
+                screen,
+                &self.input_state,
+                &self.font,
+                &mut self.settings_ui,
+                delta_time,
+            );
+            settings::settings(
+                &mut ui,
+                self.audio.as_mut(),
+                &mut self.strings,
+                &mut self.language_first,
+            );
+            ui.end();
+        }
+
+        // reset state
+        self.input_state.reset();
+    }
+
+    fn tick_game(&mut self, delta_time: f32, screen: &mut Bitmap) {
         self.time += delta_time;
 
+        if let Some(audio) = &mut self.audio {
+            audio.tick();
+
+            let screen_center = vec2(screen.width as f32, screen.height as f32) * 0.5;
+            audio.set_listener(screen_to_world_space(screen_center, &self.camera));
+        }
+
         screen.clear(0);
 
         let screen_offset = vec2(128.0, 104.0 + 32.0);
@@ -1082,7 +1243,7 @@ impl Game {
         let color_mask_uvec3 = (self.lerp_color_mask * 8.0).as_uvec3() * 32;
 
         if let Some(audio) = &self.audio {
-            audio.color_mask_sender.send(color_mask_uvec3).unwrap();
+            audio.set_music_state(self.music_state());
         }
 
         let lerped_color_mask =
@@ -1102,8 +1263,12 @@ impl Game {
         );
 
         if self.editor_mode {
-            self.tile_map
-                .editor_draw(&self.tile_set, screen, &self.camera);
+            self.tile_map.editor_draw(
+                &self.tile_set,
+                screen,
+                &self.camera,
+                self.editor_state.preview_mask,
+            );
         } else {
             self.tile_map.draw(
                 &self.tile_set,
@@ -1132,15 +1297,26 @@ impl Game {
                 20,
                 0xffff00,
             );
-            self.editor_state.tick(
+            if let Some(start) = self.editor_state.tick(
                 delta_time,
                 screen,
+                &self.font,
                 &mut self.tile_map,
                 &self.tile_set,
                 &mut self.camera,
                 &self.input_state,
-            );
+            ) {
+                self.start_playtest(start);
+            }
         } else {
+            // The active mask bounces on the beat of the music
+            let beat_bounce = self
+                .audio
+                .as_ref()
+                .map(|audio| audio.beat_position())
+                .filter(|position| position.playing && position.beat.fract() < 0.15)
+                .map_or(0, |_| 1);
+
             for i in 0..self.player_inventory.masks.len() {
                 if self.player_inventory.masks[i].color == self.color_mask {
                     self.player_inventory.masks[i]
@@ -1149,7 +1325,7 @@ impl Game {
                             screen,
                             self.player_inventory.position_on_screen.x as i32
                                 + (i as i32 + 1) * self.player_inventory.tile_size,
-                            self.player_inventory.position_on_screen.y as i32,
+                            self.player_inventory.position_on_screen.y as i32 - beat_bounce,
                         );
                 } else {
                     self.player_inventory.masks[i].sprite_inventory.draw_on(
@@ -1182,7 +1358,10 @@ impl Game {
         }
 
         for enemy in self.enemies.iter_mut() {
-            enemy.tick(delta_time, &self.tile_map, &self.tile_set);
+            let stepped = enemy.tick(delta_time, &self.tile_map, &self.tile_set);
+            if stepped && let Some(audio) = &self.audio {
+                audio.play_sfx_at("enemy_step", enemy.hitbox_aabb_world_space().center());
+            }
 
             if (!enemy.is_colored() || (self.color_mask & enemy.color_mask) & 0xffffff != 0)
                 && self
@@ -1203,13 +1382,30 @@ impl Game {
             );
         }
 
+        if self.music_mode
+            && let Some(audio) = &self.audio
+        {
+            self.jam.tick(delta_time, audio);
+            self.jam.draw(screen, &self.font);
+        }
+
         // If we won, play winning sequence
         if self.player.is_winner {
             // Just won
             self.winning_sequence_duration -= delta_time;
-            screen.draw_str(&self.font, "U WON :)", 100, 50, bitmap::GREEN);
-
-            if self.winning_sequence_duration < 0.0 {
+            let style = TextStyle {
+                color: fade_in(WINNING_SEQUENCE_DURATION - self.winning_sequence_duration)
+                    | bitmap::GREEN,
+                shadow: Some(bitmap::BLACK),
+                ..TITLE_STYLE
+            };
+            screen.draw_text(&self.title_font, self.strings.get("won"), 128, 44, &style);
+
+            if self.winning_sequence_duration < 0.0 && self.playtest.is_some() {
+                // Back to the level being edited, restarting would throw away the edits
+                self.end_playtest();
+                self.editor_mode = true;
+            } else if self.winning_sequence_duration < 0.0 {
                 self.reset_game_bool_hack = true;
                 // self.reset_game();
             } else {
@@ -1244,14 +1440,17 @@ impl Game {
                     self.death_sequence_is_playing = true;
 
                     if let Some(audio) = &self.audio {
-                        audio
-                            .sfx_sender
-                            .send((SoundTypes::DeathSound, true))
-                            .unwrap();
+                        audio.play_sfx("death");
                     }
                 }
                 self.death_sequence_duration -= delta_time;
-                screen.draw_str(&self.font, "U DIED :(", 100, 50, bitmap::RED);
+                let style = TextStyle {
+                    color: fade_in(DEATH_SEQUENCE_DURATION - self.death_sequence_duration)
+                        | bitmap::RED,
+                    shadow: Some(bitmap::BLACK),
+                    ..TITLE_STYLE
+                };
+                screen.draw_text(&self.title_font, self.strings.get("died"), 128, 44, &style);
 
                 if self.death_sequence_duration < 0.0 {
                     self.reset_game();
@@ -1273,16 +1472,21 @@ impl Game {
 
                 // after x seconds of no jumping, show key
                 if self.jump_key_hint_delay < 0.0 {
-                    if self.player_uses_controller {
-                        screen.draw_str(&self.font, "Press (Y) to jump", 80, 60, 0xdcaf00);
+                    let hint = self.strings.get(if self.player_uses_controller {
+                        "jump_hint_controller"
                     } else {
-                        screen.draw_str(&self.font, "Press (Z) to jump", 80, 60, 0xdcaf00);
-                    }
+                        "jump_hint_keyboard"
+                    });
+                    let style = TextStyle {
+                        color: fade_in(-self.jump_key_hint_delay) | 0xdcaf00,
+                        shadow: Some(bitmap::BLACK),
+                        align: Align::Center,
+                        ..Default::default()
+                    };
+                    screen.draw_text(&self.font, hint, 128, 60, &style);
                 }
             }
 
-            self.is_player_walking = false;
-
             let mut movement_axis = self.input_state.axis_state(Axis::LeftStickX);
             if movement_axis.abs() < 0.2 {
                 // keyboard input
@@ -1298,18 +1502,10 @@ impl Game {
             if movement_axis < 0.0 {
                 self.player.velocity.x = self.player.velocity.x.min(0.0);
                 self.player.velocity.x += MOVEMENT_ACCELERATION * delta_time * movement_axis;
-
-                if self.player.on_ground {
-                    self.is_player_walking = true;
-                }
             }
             if movement_axis > 0.0 {
                 self.player.velocity.x = self.player.velocity.x.max(0.0);
                 self.player.velocity.x += MOVEMENT_ACCELERATION * delta_time * movement_axis;
-
-                if self.player.on_ground {
-                    self.is_player_walking = true;
-                }
             }
 
             if movement_axis == 0.0 {
@@ -1335,10 +1531,7 @@ impl Game {
                 self.player.is_jumping = true;
 
                 if let Some(audio) = &self.audio {
-                    audio
-                        .sfx_sender
-                        .send((SoundTypes::JumpSound, true))
-                        .unwrap();
+                    audio.play_sfx("jump");
                 }
             }
             if self.input_state.is_key_down(Key::Jump) {
@@ -1601,29 +1794,26 @@ impl Game {
                 }
                 self.player.on_ground = tile_collision_below;
             }
-            self.player.tick(delta_time);
-
-            if self.is_player_walking && !self.was_player_walking {
-                // send signal to start playing walking sound
-                if let Some(audio) = &self.audio {
-                    audio
-                        .sfx_sender
-                        .send((SoundTypes::FootstepSound, true))
-                        .unwrap();
-                }
-            } else if self.was_player_walking && !self.is_player_walking {
-                // send signal to stop playing walking sound
-                if let Some(audio) = &self.audio {
-                    audio
-                        .sfx_sender
-                        .send((SoundTypes::FootstepSound, false))
-                        .unwrap();
+            // Animation events are named after the sound effect they play
+            let events = self.player.tick(delta_time);
+            if let Some(audio) = &self.audio {
+                for event in events {
+                    audio.play_sfx(&event);
                 }
             }
-
-            self.was_player_walking = self.is_player_walking;
         }
 
+        if self.color_mask & 0xffffff != 0 {
+            let center = self.player.aabb_world_space().center();
+            let pos = world_space_to_screen_space(center, &self.camera) - vec2(16.0, 16.0);
+            let tint = PLAYER_GLOW_ALPHA << 24 | (self.color_mask & 0xffffff);
+            self.player_glow.draw_on_with(
+                screen,
+                pos.x as i32,
+                pos.y as i32,
+                BlendMode::Tint(tint),
+            );
+        }
         self.player.draw(screen, &self.camera, self.color_mask);
 
         // Loop over savepoints
@@ -1639,6 +1829,16 @@ impl Game {
                     .sprite_save_off
                     .draw_on(screen, pos.x as i32, pos.y as i32);
             }
+            if !savepoint.activated {
+                savepoint.chime_timer -= delta_time;
+                if savepoint.chime_timer < 0.0 {
+                    savepoint.chime_timer += SaveGamePoint::CHIME_INTERVAL;
+                    if let Some(audio) = &self.audio {
+                        audio.play_sfx_at("chime", savepoint.aabb_world_space().center());
+                    }
+                }
+            }
+
             // Save and turn on if position overlaps with player
             if savepoint
                 .aabb_world_space()
@@ -1650,11 +1850,10 @@ impl Game {
                         .sprite_save_on
                         .draw_on(screen, pos.x as i32, pos.y as i32);
 
+                    let center = savepoint.aabb_world_space().center();
+                    self.particles.burst(center, 0xffdcaf00, 12);
                     if let Some(audio) = &self.audio {
-                        audio
-                            .sfx_sender
-                            .send((SoundTypes::PickupSound, true))
-                            .unwrap();
+                        audio.play_sfx_at("pickup", center);
                     }
                 };
 
@@ -1665,13 +1864,16 @@ impl Game {
         // Loop over masks
         for mask in self.mask_game_objects.iter_mut() {
             if mask.visible {
+                // Masks lying around rock back and forth to catch the eye
                 let pos = world_space_to_screen_space(mask.position, &self.camera);
-                mask.sprite_scene.draw_on_scaled(
+                let sprite = &mask.sprite_scene;
+                let pivot = vec2(sprite.width as f32, sprite.height as f32) * 0.5;
+                let angle = (self.time * MASK_ROCK_SPEED).sin() * MASK_ROCK_ANGLE;
+                let transform = Mat2::from_scale_angle(Vec2::splat(self.camera.zoom), angle);
+                sprite.draw_on_transformed(
                     screen,
-                    pos.x as i32,
-                    pos.y as i32,
-                    self.camera.zoom,
-                    self.camera.zoom,
+                    sprite_transform(pos + pivot * self.camera.zoom, pivot, transform),
+                    BlendMode::Alpha,
                 );
 
                 // Add to collection
@@ -1681,25 +1883,32 @@ impl Game {
                 {
                     self.color_mask = mask.color;
                     mask.visible = false;
+                    self.particles
+                        .burst(mask.aabb_world_space().center(), mask.color, 24);
 
                     // Special case for the golden mask
                     if mask.color == bitmap::YELLOW {
                         self.player.is_winner = true;
+                        if let Some(audio) = &self.audio {
+                            audio.switch_song("victory", Quantize::Bar);
+                        }
                         return;
                     }
 
                     self.player_inventory.masks.push(mask.clone());
 
+                    // The new layer of the music comes in on the next bar, the stinger leads into it
                     if let Some(audio) = &self.audio {
-                        audio
-                            .sfx_sender
-                            .send((SoundTypes::PickupSound, true))
-                            .unwrap();
+                        audio.play_sfx("pickup");
+                        audio.play_stinger("stinger", Quantize::Beat);
                     }
                 }
             }
         }
 
+        self.particles.tick(delta_time);
+        self.particles.draw(screen, &self.camera);
+
         if should_save {
             self.save_state = Some(self.build_save_state());
         }
@@ -1721,7 +1930,5 @@ impl Game {
                 0xffff00,
             );
         }
-        // reset state
-        self.input_state.reset();
     }
 }
@@ -0,0 +1,160 @@
+// Picks the clip of an `Atlas` to play from named parameters set by the game, using the
+// `transition` rules of the atlas
+use crate::game::sprite::Sprite;
+
+#[derive(Debug, Clone)]
+enum Condition {
+    Set(String), // parameter is not zero
+    Above(String, f32),
+    Below(String, f32),
+    Finished, // the current clip played to its end
+}
+
+impl Condition {
+    // Returns the condition and whether it has to hold, `!` negates it
+    fn parse(token: &str) -> Option<(Self, bool)> {
+        if let Some(token) = token.strip_prefix('!') {
+            return Self::parse(token).map(|(condition, expected)| (condition, !expected));
+        }
+        let condition = if let Some((name, value)) = token.split_once('>') {
+            Self::Above(name.to_owned(), value.parse().ok()?)
+        } else if let Some((name, value)) = token.split_once('<') {
+            Self::Below(name.to_owned(), value.parse().ok()?)
+        } else if token == "finished" {
+            Self::Finished
+        } else if !token.is_empty() {
+            Self::Set(token.to_owned())
+        } else {
+            return None;
+        };
+        Some((condition, true))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Transition {
+    from: Option<usize>, // clip index, None applies to every clip
+    to: usize,
+    conditions: Vec<(Condition, bool)>,
+}
+
+impl Transition {
+    // transition <from|*> <to> [when <conditions>...]
+    pub(crate) fn parse(args: &[&str], clip_names: &[&str]) -> Result<Self, String> {
+        if args.len() < 2 {
+            return Err("expects 2 arguments".to_owned());
+        }
+        let (from, to, rest) = (args[0], args[1], &args[2..]);
+
+        let conditions = match rest.split_first() {
+            None => Vec::new(),
+            Some((&"when", conditions)) => conditions
+                .iter()
+                .map(|&token| {
+                    Condition::parse(token)
+                        .ok_or_else(|| format!("invalid condition \"{}\"", token))
+                })
+                .collect::<Result<_, _>>()?,
+            Some((token, _)) => return Err(format!("unexpected \"{}\"", token)),
+        };
+
+        let clip = |name: &str| {
+            clip_names
+                .iter()
+                .position(|&n| n == name)
+                .ok_or_else(|| format!("unknown clip \"{}\"", name))
+        };
+        Ok(Self {
+            from: if from == "*" { None } else { Some(clip(from)?) },
+            to: clip(to)?,
+            conditions,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Animator {
+    clips: Vec<(String, Sprite)>,
+    transitions: Vec<Transition>,
+    params: Vec<(String, f32)>,
+    current: usize,
+}
+
+impl Animator {
+    // Starts with the first clip
+    pub fn new(clips: Vec<(String, Sprite)>, transitions: Vec<Transition>) -> Self {
+        Self {
+            clips,
+            transitions,
+            params: Vec::new(),
+            current: 0,
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: f32) {
+        if let Some(param) = self.params.iter_mut().find(|(n, _)| n == name) {
+            param.1 = value;
+        } else {
+            self.params.push((name.to_owned(), value));
+        }
+    }
+
+    pub fn set_bool(&mut self, name: &str, value: bool) {
+        self.set(name, if value { 1.0 } else { 0.0 });
+    }
+
+    fn param(&self, name: &str) -> f32 {
+        self.params
+            .iter()
+            .find(|(n, _)| n == name)
+            .map_or(0.0, |(_, value)| *value)
+    }
+
+    fn matches(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Set(name) => self.param(name) != 0.0,
+            Condition::Above(name, value) => self.param(name) > *value,
+            Condition::Below(name, value) => self.param(name) < *value,
+            Condition::Finished => self.sprite().finished,
+        }
+    }
+
+    pub fn sprite(&self) -> &Sprite {
+        &self.clips[self.current].1
+    }
+
+    // Switches to the clip right away, returns the event of its first frame
+    pub fn play(&mut self, name: &str) -> Option<String> {
+        let index = self.clips.iter().position(|(n, _)| n == name)?;
+        self.current = index;
+        self.clips[index].1.restart()
+    }
+
+    // The first rule that applies to the current clip decides what plays next, a rule
+    // leading to the clip that already plays keeps it going.
+    // Returns the events of the frames that were reached
+    pub fn tick(&mut self, delta_time: f32) -> Vec<String> {
+        let next = self
+            .transitions
+            .iter()
+            .find(|transition| {
+                transition.from.is_none_or(|from| from == self.current)
+                    && transition
+                        .conditions
+                        .iter()
+                        .all(|(condition, expected)| self.matches(condition) == *expected)
+            })
+            .map(|transition| transition.to);
+
+        let mut events = Vec::new();
+        if let Some(next) = next
+            && next != self.current
+        {
+            self.current = next;
+            events.extend(self.clips[next].1.restart());
+        }
+
+        events.extend(self.clips[self.current].1.tick(delta_time));
+        events
+    }
+}
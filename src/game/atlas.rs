@@ -0,0 +1,269 @@
+// Named animations cut out of a sprite sheet, described by a text file next to the image.
+// See assets/sprite/spritesheet_animation.txt for the format
+use super::Aabb;
+use crate::bitmap::Bitmap;
+use crate::game::animator::{Animator, Transition};
+use crate::game::sprite::{Frame, Playback, Sprite};
+use glam::*;
+
+const DEFAULT_DURATION: f32 = 1.0 / 12.0;
+
+pub struct Atlas {
+    clips: Vec<(String, Sprite)>,
+    transitions: Vec<Transition>,
+}
+
+fn parse_number(value: &str, line: usize) -> Result<f32, String> {
+    value
+        .parse()
+        .map_err(|_| format!("line {}: invalid number \"{}\"", line, value))
+}
+
+// Frames have to last a while or an animation never gets past them
+fn parse_positive(value: &str, line: usize) -> Result<f32, String> {
+    let number = parse_number(value, line)?;
+    if number > 0.0 {
+        Ok(number)
+    } else {
+        Err(format!("line {}: \"{}\" has to be above 0", line, value))
+    }
+}
+
+fn parse_numbers<const N: usize>(values: &[&str], line: usize) -> Result<[f32; N], String> {
+    if values.len() != N {
+        return Err(format!(
+            "line {}: expected {} numbers, got {}",
+            line,
+            N,
+            values.len()
+        ));
+    }
+    let mut numbers = [0.0; N];
+    for (number, value) in numbers.iter_mut().zip(values) {
+        *number = parse_number(value, line)?;
+    }
+    Ok(numbers)
+}
+
+fn parse_hitbox(values: &[&str], line: usize) -> Result<Aabb, String> {
+    let [x0, y0, x1, y1] = parse_numbers(values, line)?;
+    Ok(Aabb {
+        min: vec2(x0, y0),
+        max: vec2(x1, y1),
+    })
+}
+
+impl Atlas {
+    // Loads the image at `path` together with the descriptor of the same name ending in .txt
+    pub fn load(path: &str) -> Self {
+        let sheet = Bitmap::load(path);
+        let descriptor = std::path::Path::new(path).with_extension("txt");
+        let text = std::fs::read_to_string(&descriptor).unwrap_or_else(|err| {
+            panic!(
+                "Failed to load atlas: \"{}\". Error: {}",
+                descriptor.display(),
+                err
+            )
+        });
+        Self::parse(&text, &sheet).unwrap_or_else(|err| {
+            panic!(
+                "Failed to parse atlas: \"{}\". Error: {}",
+                descriptor.display(),
+                err
+            )
+        })
+    }
+
+    pub fn parse(text: &str, sheet: &Bitmap) -> Result<Self, String> {
+        let mut clips: Vec<(String, Sprite)> = Vec::new();
+
+        let mut size = (16, 16);
+        let mut pivot: Option<Vec2> = None; // center of the frame when not set
+        let mut hitbox: Option<Aabb> = None; // whole frame when not set
+        let mut duration = DEFAULT_DURATION; // of the frames of the current clip
+        let mut transition_lines = Vec::new(); // parsed once all clips are known
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap_or_default();
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let Some((&command, args)) = words.split_first() else {
+                continue;
+            };
+
+            match command {
+                "size" => {
+                    let [w, h] = parse_numbers(args, line_number)?;
+                    size = (w as usize, h as usize);
+                }
+                "pivot" => {
+                    let [x, y] = parse_numbers(args, line_number)?;
+                    pivot = Some(vec2(x, y));
+                }
+                "hitbox" => hitbox = Some(parse_hitbox(args, line_number)?),
+                "clip" => {
+                    let (&name, options) = args
+                        .split_first()
+                        .ok_or_else(|| format!("line {}: clip without a name", line_number))?;
+                    let mut playback = Playback::Loop;
+                    duration = DEFAULT_DURATION;
+                    for option in options {
+                        match option.split_once('=') {
+                            Some(("fps", value)) => {
+                                duration = 1.0 / parse_positive(value, line_number)?
+                            }
+                            Some(("playback", value)) => {
+                                playback = Playback::from_name(value).ok_or_else(|| {
+                                    format!("line {}: unknown playback \"{}\"", line_number, value)
+                                })?
+                            }
+                            _ => {
+                                return Err(format!(
+                                    "line {}: unknown clip setting \"{}\"",
+                                    line_number, option
+                                ));
+                            }
+                        }
+                    }
+                    clips.push((
+                        name.to_owned(),
+                        Sprite {
+                            frames: Vec::new(),
+                            frame_index: 0,
+                            t: 0.0,
+                            playback,
+                            reversed: false,
+                            finished: false,
+                        },
+                    ));
+                }
+                "frame" | "row" => {
+                    let Some((_, sprite)) = clips.last_mut() else {
+                        return Err(format!("line {}: frame outside of a clip", line_number));
+                    };
+
+                    let (positions, options) = if command == "row" {
+                        let [x, y, count] =
+                            parse_numbers(args.get(..3).unwrap_or(args), line_number)?;
+                        let positions = (0..count as i32)
+                            .map(|i| (x as i32 + i * size.0 as i32, y as i32))
+                            .collect::<Vec<_>>();
+                        (positions, &args[3..])
+                    } else {
+                        let [x, y] = parse_numbers(args.get(..2).unwrap_or(args), line_number)?;
+                        (vec![(x as i32, y as i32)], &args[2..])
+                    };
+
+                    let mut frame_duration = duration;
+                    let mut frame_pivot = pivot;
+                    let mut frame_hitbox = hitbox.clone();
+                    for option in options {
+                        let (key, value) = option.split_once('=').ok_or_else(|| {
+                            format!(
+                                "line {}: expected key=value, got \"{}\"",
+                                line_number, option
+                            )
+                        })?;
+                        let values = value.split(',').collect::<Vec<_>>();
+                        match key {
+                            "duration" => frame_duration = parse_positive(value, line_number)?,
+                            "pivot" => {
+                                let [x, y] = parse_numbers(&values, line_number)?;
+                                frame_pivot = Some(vec2(x, y));
+                            }
+                            "hitbox" => frame_hitbox = Some(parse_hitbox(&values, line_number)?),
+                            _ => {
+                                return Err(format!(
+                                    "line {}: unknown frame setting \"{}\"",
+                                    line_number, key
+                                ));
+                            }
+                        }
+                    }
+
+                    for (x, y) in positions {
+                        if x < 0
+                            || y < 0
+                            || x as usize + size.0 > sheet.width
+                            || y as usize + size.1 > sheet.height
+                        {
+                            return Err(format!(
+                                "line {}: frame at {} {} is outside of the sheet",
+                                line_number, x, y
+                            ));
+                        }
+
+                        let mut bitmap = Bitmap::new(size.0, size.1);
+                        sheet.draw_on(&mut bitmap, -x, -y);
+                        let frame_size = vec2(size.0 as f32, size.1 as f32);
+                        sprite.frames.push(Frame {
+                            bitmap,
+                            duration: frame_duration,
+                            pivot: frame_pivot.unwrap_or(frame_size * 0.5),
+                            hitbox: frame_hitbox.clone().unwrap_or(Aabb {
+                                min: Vec2::ZERO,
+                                max: frame_size,
+                            }),
+                            event: None,
+                        });
+                    }
+                }
+                "event" => {
+                    let Some((_, sprite)) = clips.last_mut() else {
+                        return Err(format!("line {}: event outside of a clip", line_number));
+                    };
+                    let [frame, name] = args else {
+                        return Err(format!(
+                            "line {}: expected a frame and an event name",
+                            line_number
+                        ));
+                    };
+                    let frame = parse_number(frame, line_number)? as usize;
+                    let frame = sprite.frames.get_mut(frame).ok_or_else(|| {
+                        format!("line {}: clip has no frame {}", line_number, frame)
+                    })?;
+                    frame.event = Some(name.to_string());
+                }
+                "transition" => transition_lines.push((line_number, args.to_vec())),
+                _ => {
+                    return Err(format!(
+                        "line {}: unknown command \"{}\"",
+                        line_number, command
+                    ));
+                }
+            }
+        }
+
+        if let Some((name, _)) = clips.iter().find(|(_, sprite)| sprite.frames.is_empty()) {
+            return Err(format!("clip \"{}\" has no frames", name));
+        }
+
+        let clip_names = clips
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        let transitions = transition_lines
+            .iter()
+            .map(|(line, args)| {
+                Transition::parse(args, &clip_names)
+                    .map_err(|err| format!("line {}: {}", line, err))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { clips, transitions })
+    }
+
+    // Plays the clips following the `transition` rules, starting with the first clip
+    pub fn animator(&self) -> Animator {
+        Animator::new(self.clips.clone(), self.transitions.clone())
+    }
+
+    // Every sprite gets its own copy so it animates independently
+    pub fn sprite(&self, name: &str) -> Sprite {
+        self.clips
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, sprite)| sprite.clone())
+            .unwrap_or_else(|| panic!("Unknown animation: \"{}\"", name))
+    }
+}
@@ -1,11 +1,19 @@
-use super::{Aabb, MouseButton, draw_aabb_ws};
+use super::{Aabb, MouseButton, SaveGamePoint, SaveState, draw_aabb_ws};
 use crate::{
     Bitmap,
+    bitmap::{self, Font},
     game::{
         InputState, Key,
+        atlas::Atlas,
         camera::{Camera, screen_to_world_space, world_space_to_screen_space},
-        draw_aabb_ss,
+        enemy::Enemy,
+        inspector::inspect,
+        overlays::{self, Overlays},
+        palette::TilePalette,
+        reachability::{self, MASK_STATES, Reach, Spawn},
+        selection::{Block, CLIPBOARD_PATH, Selection},
         tilemap::{TileMap, TileSet},
+        ui::{Ui, UiState, rect},
     },
 };
 use glam::*;
@@ -21,24 +29,166 @@ pub(crate) enum ObjectType {
     Savepoint,
 }
 
+impl ObjectType {
+    const ALL: [Self; 5] = [
+        Self::WhiteHedgehog,
+        Self::RedHedgehog,
+        Self::GreenHedgehog,
+        Self::BlueHedgehog,
+        Self::Savepoint,
+    ];
+
+    // Name in the level object files
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::WhiteHedgehog => "white_hedgehog",
+            Self::RedHedgehog => "red_hedgehog",
+            Self::GreenHedgehog => "green_hedgehog",
+            Self::BlueHedgehog => "blue_hedgehog",
+            Self::Savepoint => "savepoint",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|object_type| object_type.name() == name)
+    }
+
+    pub(crate) fn is_hedgehog(self) -> bool {
+        self != Self::Savepoint
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ObjectProperties {
+    pub(crate) going_left: bool,
+    pub(crate) speed: f32,
+    pub(crate) patrol: Option<(f32, f32)>, // how far to walk to the left and right of the spawn
+    pub(crate) id: String,                 // name to refer to the object by, can be empty
+}
+
+impl Default for ObjectProperties {
+    fn default() -> Self {
+        Self {
+            going_left: false,
+            speed: Enemy::DEFAULT_SPEED,
+            patrol: None,
+            id: String::new(),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub(crate) struct ObjectSpawn {
     pub(crate) position: Vec2,
     pub(crate) aabb: Aabb,
     pub(crate) object_type: ObjectType,
+    pub(crate) properties: ObjectProperties,
+}
+
+impl ObjectSpawn {
+    // The object as a line of the level object file, at `position`
+    pub(crate) fn describe(&self, position: Vec2) -> String {
+        let mut line = format!("{} {} {}", self.object_type.name(), position.x, position.y);
+        let properties = &self.properties;
+        if self.object_type.is_hedgehog() {
+            if properties.going_left {
+                line += " direction=left";
+            }
+            if properties.speed != Enemy::DEFAULT_SPEED {
+                line += &format!(" speed={}", properties.speed);
+            }
+            if let Some((left, right)) = properties.patrol {
+                line += &format!(" patrol={},{}", left, right);
+            }
+        }
+        if !properties.id.is_empty() {
+            line += &format!(" id={}", properties.id);
+        }
+        line
+    }
+}
+
+const LEVEL_PATH: &str = "assets/level0.txt";
+
+// The objects of assets/level0.txt are kept in assets/level0.objects.txt
+fn objects_path(level_path: &str) -> std::path::PathBuf {
+    std::path::Path::new(level_path).with_extension("objects.txt")
+}
+
+// <type> <x> <y> [direction=left|right] [speed=<n>] [patrol=<left>,<right>] [id=<name>]
+pub(crate) fn parse_object(
+    words: &[&str],
+    line_number: usize,
+) -> Result<(ObjectType, Vec2, ObjectProperties), String> {
+    let [name, x, y, options @ ..] = words else {
+        return Err(format!(
+            "line {}: expected a type and a position",
+            line_number
+        ));
+    };
+
+    let object_type = ObjectType::from_name(name)
+        .ok_or_else(|| format!("line {}: unknown object \"{}\"", line_number, name))?;
+    let number = |value: &str| {
+        value
+            .parse::<f32>()
+            .map_err(|_| format!("line {}: invalid number \"{}\"", line_number, value))
+    };
+    let position = vec2(number(x)?, number(y)?);
+
+    let mut properties = ObjectProperties::default();
+    for option in options {
+        match option.split_once('=') {
+            Some(("direction", "left")) => properties.going_left = true,
+            Some(("direction", "right")) => properties.going_left = false,
+            Some(("speed", value)) => properties.speed = number(value)?,
+            Some(("patrol", value)) => {
+                let (left, right) = value.split_once(',').ok_or_else(|| {
+                    format!("line {}: expected patrol=<left>,<right>", line_number)
+                })?;
+                properties.patrol = Some((number(left)?, number(right)?));
+            }
+            Some(("id", value)) => properties.id = value.to_owned(),
+            _ => {
+                return Err(format!(
+                    "line {}: unknown object setting \"{}\"",
+                    line_number, option
+                ));
+            }
+        }
+    }
+    Ok((object_type, position, properties))
+}
+
+// One object per line, see `parse_object`
+fn parse_objects(text: &str) -> Result<Vec<(ObjectType, Vec2, ObjectProperties)>, String> {
+    let mut objects = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        if !words.is_empty() {
+            objects.push(parse_object(&words, index + 1)?);
+        }
+    }
+    Ok(objects)
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(usize)]
+#[allow(clippy::enum_variant_names)]
 pub enum EditorMode {
     #[default]
     TileMode,
     ObjectMode,
+    SelectMode,
 }
 
 pub(crate) struct ObjectButton {
     pub(crate) object_type: ObjectType,
     pub(crate) icon_bitmap: Bitmap,
-    pub(crate) icon_scale: f32,
+    pub(crate) hitbox: Aabb, // what the player touches in the game
 }
 
 pub struct EditorState {
@@ -46,80 +196,322 @@ pub struct EditorState {
 
     // tile mode
     pub(crate) selected_tile: u32,
+    palette: TilePalette,
 
     // object mode
     pub(crate) selected_object: u32,
     pub(crate) object_spawns: Vec<ObjectSpawn>,
     pub(crate) object_buttons: Vec<ObjectButton>,
+    object_list_first: usize, // top row of the object list in the toolbar
 
     held_object: Option<usize>,
-}
+    inspected_object: Option<usize>,
+
+    // select mode
+    selection: Option<Selection>,
+    marquee_start: Option<IVec2>,
+    dragged: Option<(Block, IVec2)>, // lifted out of the level, and the tile it is held by
 
-fn extract_sprite_from_sheet(sheet: &Bitmap, x: i32, y: i32, w: usize, h: usize) -> Bitmap {
-    let mut icon = Bitmap::new(w, h);
-    sheet.draw_on(&mut icon, -x, -y);
-    icon
+    playtest_masks: [bool; 3], // red, green and blue mask in the inventory when play-testing
+
+    overlays: Overlays,
+    pub(crate) preview_mask: bitmap::ColorChannel, // the level is shown as when wearing it
+    spawn: Spawn,
+    reachability: Option<(Vec<u32>, Vec<Reach>)>, // for the tiles, per mask state
+
+    ui: UiState,
 }
 
 impl EditorState {
-    pub fn new(enemy_sprite_sheet: &Bitmap, savepoint_bitmap: Bitmap) -> Self {
-        let white_hedgehog_icon = extract_sprite_from_sheet(enemy_sprite_sheet, 0, 0, 16, 8);
-        let red_hedgehog_icon = extract_sprite_from_sheet(enemy_sprite_sheet, 0, 8, 16, 8);
-        let green_hedgehog_icon = extract_sprite_from_sheet(enemy_sprite_sheet, 0, 16, 16, 8);
-        let blue_hedgehog_icon = extract_sprite_from_sheet(enemy_sprite_sheet, 0, 24, 16, 8);
-        let object_buttons = vec![
+    pub fn new(enemy_atlas: &Atlas, savepoint_bitmap: Bitmap, spawn: Spawn) -> Self {
+        let hedgehog = |object_type, name| {
+            let frame = &enemy_atlas.sprite(name).frames[0];
             ObjectButton {
-                object_type: ObjectType::WhiteHedgehog,
-                icon_bitmap: white_hedgehog_icon,
-                icon_scale: 1.0,
-            },
-            ObjectButton {
-                object_type: ObjectType::RedHedgehog,
-                icon_bitmap: red_hedgehog_icon,
-                icon_scale: 1.0,
-            },
-            ObjectButton {
-                object_type: ObjectType::GreenHedgehog,
-                icon_bitmap: green_hedgehog_icon,
-                icon_scale: 1.0,
-            },
-            ObjectButton {
-                object_type: ObjectType::BlueHedgehog,
-                icon_bitmap: blue_hedgehog_icon,
-                icon_scale: 1.0,
-            },
+                object_type,
+                icon_bitmap: frame.bitmap.clone(),
+                hitbox: frame.hitbox.clone(),
+            }
+        };
+        let object_buttons = vec![
+            hedgehog(ObjectType::WhiteHedgehog, "white"),
+            hedgehog(ObjectType::RedHedgehog, "red"),
+            hedgehog(ObjectType::GreenHedgehog, "green"),
+            hedgehog(ObjectType::BlueHedgehog, "blue"),
             ObjectButton {
                 object_type: ObjectType::Savepoint,
                 icon_bitmap: savepoint_bitmap,
-                icon_scale: 1.0,
+                hitbox: SaveGamePoint::AABB,
             },
         ];
 
         Self {
             editor_mode: Default::default(),
             selected_tile: Default::default(),
+            palette: TilePalette::new(),
             selected_object: Default::default(),
             object_spawns: Default::default(),
             object_buttons,
+            object_list_first: 0,
             held_object: None,
+            inspected_object: None,
+            selection: None,
+            marquee_start: None,
+            dragged: None,
+            playtest_masks: [false; 3],
+            overlays: Overlays::empty(),
+            preview_mask: bitmap::WHITE,
+            spawn,
+            reachability: None,
+            ui: UiState::default(),
+        }
+        .with_objects_of(LEVEL_PATH)
+    }
+
+    // Text fields take the keyboard, keys shouldn't do anything else then
+    pub fn is_typing(&self) -> bool {
+        self.ui.wants_keyboard()
+    }
+
+    fn spawn_aabb(&self, object_type: ObjectType) -> Aabb {
+        let icon = &self
+            .object_buttons
+            .iter()
+            .find(|button| button.object_type == object_type)
+            .expect("Every object type has a button")
+            .icon_bitmap;
+        Aabb {
+            min: Vec2::ZERO,
+            max: vec2((icon.width - 1) as f32, (icon.height - 1) as f32),
+        }
+    }
+
+    fn draw_object(
+        &self,
+        screen: &mut Bitmap,
+        camera: &Camera,
+        object_type: ObjectType,
+        position: Vec2,
+    ) {
+        // TODO(manon): Linear search for every object *PUKE*
+        if let Some(button) = self
+            .object_buttons
+            .iter()
+            .find(|button| button.object_type == object_type)
+        {
+            let position = world_space_to_screen_space(position, camera);
+            button.icon_bitmap.draw_on_scaled(
+                screen,
+                position.x as i32,
+                position.y as i32,
+                camera.zoom,
+                camera.zoom,
+            );
+        }
+    }
+
+    // A level without an object file has no objects
+    fn with_objects_of(mut self, level_path: &str) -> Self {
+        let path = objects_path(level_path);
+        let Ok(text) = std::fs::read_to_string(&path) else {
+            return self;
+        };
+        let objects = parse_objects(&text).unwrap_or_else(|err| {
+            panic!(
+                "Failed to parse level objects: \"{}\". Error: {}",
+                path.display(),
+                err
+            )
+        });
+        for (object_type, position, properties) in objects {
+            self.object_spawns.push(ObjectSpawn {
+                position,
+                aabb: self.spawn_aabb(object_type),
+                object_type,
+                properties,
+            });
+        }
+        self
+    }
+
+    fn store_objects(&self, level_path: &str) {
+        let mut data = String::from(
+            "# <type> <x> <y> [direction=left] [speed=<n>] [patrol=<left>,<right>] [id=<name>]\n",
+        );
+        for spawn in &self.object_spawns {
+            data += &spawn.describe(spawn.position);
+            data.push('\n');
+        }
+        std::fs::write(objects_path(level_path), data).unwrap();
+    }
+
+    // Dragging selects a block of the level, dragging the selection moves it.
+    // C copies, X cuts, V pastes at the mouse, Delete clears, H and J flip and R rotates
+    fn tick_select_mode(
+        &mut self,
+        screen: &mut Bitmap,
+        tile_map: &mut TileMap,
+        tile_set: &TileSet,
+        camera: &Camera,
+        input_state: &InputState,
+        shortcuts: bool,
+    ) {
+        let tile_size = tile_map.tile_size;
+        let mouse_ws = screen_to_world_space(input_state.mouse, camera);
+        let mouse_tile = (mouse_ws / tile_size as f32).floor().as_ivec2();
+
+        if input_state.is_mouse_pressed(MouseButton::Left) && !self.ui.wants_mouse() {
+            match self.selection {
+                Some(selection) if selection.contains(mouse_tile) => {
+                    let block = Block::cut(tile_map, &mut self.object_spawns, &selection);
+                    self.dragged = Some((block, mouse_tile - selection.min));
+                    self.inspected_object = None;
+                }
+                _ => {
+                    self.marquee_start = Some(mouse_tile);
+                    self.selection = None;
+                }
+            }
+        }
+        if input_state.is_mouse_down(MouseButton::Left) {
+            if let Some(start) = self.marquee_start {
+                self.selection = Some(Selection::between(start, mouse_tile));
+            }
+            if let (Some((_, grab)), Some(selection)) = (&self.dragged, &mut self.selection) {
+                selection.min = mouse_tile - *grab;
+            }
+        } else {
+            self.marquee_start = None;
+            if let Some((block, _)) = self.dragged.take() {
+                let selection = self.selection.expect("A dragged block is selected");
+                block.place(tile_map, &mut self.object_spawns, selection.min);
+            }
+        }
+        // The selection stays while a block is held, it is where the block gets dropped
+        if input_state.is_mouse_pressed(MouseButton::Right)
+            && !self.ui.wants_mouse()
+            && self.dragged.is_none()
+        {
+            self.selection = None;
+        }
+
+        if shortcuts && self.dragged.is_none() {
+            if input_state.is_key_pressed(Key::EditorPaste) {
+                let text = std::fs::read_to_string(CLIPBOARD_PATH).unwrap_or_default();
+                match Block::parse(&text, tile_set.tiles.len(), |object_type| {
+                    self.spawn_aabb(object_type)
+                }) {
+                    Ok(block) => {
+                        block.place(tile_map, &mut self.object_spawns, mouse_tile);
+                        self.selection = Some(Selection {
+                            min: mouse_tile,
+                            size: block.size,
+                        });
+                    }
+                    Err(err) => println!("Nothing to paste: {}", err),
+                }
+            }
+
+            if let Some(selection) = &mut self.selection {
+                if input_state.is_key_pressed(Key::EditorCopy) {
+                    Block::copy(tile_map, &self.object_spawns, selection)
+                        .store_to_file(CLIPBOARD_PATH);
+                }
+
+                let transform: Option<fn(&mut Block, u32)> =
+                    if input_state.is_key_pressed(Key::EditorFlipX) {
+                        Some(Block::flip_x)
+                    } else if input_state.is_key_pressed(Key::EditorFlipY) {
+                        Some(Block::flip_y)
+                    } else if input_state.is_key_pressed(Key::EditorRotate) {
+                        Some(Block::rotate)
+                    } else {
+                        None
+                    };
+                let cut = input_state.is_key_pressed(Key::EditorCut);
+                let delete = input_state.is_key_pressed(Key::EditorDelete);
+
+                if transform.is_some() || cut || delete {
+                    let mut block = Block::cut(tile_map, &mut self.object_spawns, selection);
+                    self.inspected_object = None;
+                    if let Some(transform) = transform {
+                        transform(&mut block, tile_size);
+                        block.place(tile_map, &mut self.object_spawns, selection.min);
+                        selection.size = block.size;
+                    } else if cut {
+                        block.store_to_file(CLIPBOARD_PATH);
+                    }
+                }
+            }
+        }
+
+        for object in self.object_spawns.iter() {
+            self.draw_object(screen, camera, object.object_type, object.position);
+        }
+
+        // The block being moved isn't part of the level until it is dropped
+        if let (Some((block, _)), Some(selection)) = (&self.dragged, &self.selection) {
+            let origin = selection.min.as_vec2() * tile_size as f32;
+            for y in 0..block.size.y {
+                for x in 0..block.size.x {
+                    let tile = block.tiles[(x + y * block.size.x) as usize];
+                    if tile != 0 {
+                        let position = origin + ivec2(x, y).as_vec2() * tile_size as f32;
+                        let position = world_space_to_screen_space(position, camera);
+                        tile_set.tiles[(tile - 1) as usize].draw_on_scaled(
+                            screen,
+                            position.x.floor() as i32,
+                            position.y.floor() as i32,
+                            camera.zoom,
+                            camera.zoom,
+                        );
+                    }
+                }
+            }
+            for object in &block.objects {
+                self.draw_object(screen, camera, object.object_type, origin + object.position);
+            }
+        }
+
+        if let Some(selection) = &self.selection {
+            draw_aabb_ws(screen, &selection.world_aabb(tile_size), camera, 0xdcaf00);
+        }
+    }
+
+    // Where and with which masks to start playing
+    fn playtest_start(&self, position: Vec2) -> SaveState {
+        let [has_red_mask, has_green_mask, has_blue_mask] = self.playtest_masks;
+        SaveState {
+            player_position: position,
+            has_red_mask,
+            has_green_mask,
+            has_blue_mask,
+            color_mask: bitmap::BLACK,
         }
     }
 
+    // Returns the start of a play-test when one is requested
+    #[allow(clippy::too_many_arguments)]
     pub fn tick(
         &mut self,
         delta_time: f32,
         screen: &mut Bitmap,
+        font: &Font,
         tile_map: &mut TileMap,
         tile_set: &TileSet,
         camera: &mut Camera,
         input_state: &InputState,
-    ) {
-        if input_state.is_key_pressed(Key::S) {
-            tile_map.store_to_file("assets/level0.txt");
-            println!("Level Saved!");
+    ) -> Option<SaveState> {
+        // Letters go to the text field being typed in instead
+        let shortcuts = !self.ui.wants_keyboard();
+        let mut save = shortcuts && input_state.is_key_pressed(Key::S);
+
+        // P plays from the mouse, the play button from the middle of the screen
+        let mut playtest = None;
+        if shortcuts && input_state.is_key_pressed(Key::EditorPlay) && !self.ui.wants_mouse() {
+            playtest = Some(screen_to_world_space(input_state.mouse, camera));
         }
 
-        if input_state.mouse_scroll_delta.y != 0.0 {
+        if input_state.mouse_scroll_delta.y != 0.0 && !self.ui.wants_mouse() {
             let scroll_amount = (input_state.mouse_scroll_delta.y / 12.0).clamp(-1.0, 1.0);
             camera.zoom = (camera.zoom * 2.0f32.powf(scroll_amount)).clamp(0.125, 2.0);
         }
@@ -150,12 +542,15 @@ impl EditorState {
             camera.position.y += delta_time * editor_speed;
         }
 
-        if input_state.is_key_pressed(Key::Key1) {
+        if shortcuts && input_state.is_key_pressed(Key::Key1) {
             self.editor_mode = EditorMode::TileMode;
         }
-        if input_state.is_key_pressed(Key::Key2) {
+        if shortcuts && input_state.is_key_pressed(Key::Key2) {
             self.editor_mode = EditorMode::ObjectMode;
         }
+        if shortcuts && input_state.is_key_pressed(Key::Key3) {
+            self.editor_mode = EditorMode::SelectMode;
+        }
 
         // Draw level bounds
         let aabb = Aabb {
@@ -164,18 +559,52 @@ impl EditorState {
         };
         draw_aabb_ws(screen, &aabb, camera, 0x00ff00);
 
+        if self.overlays.contains(Overlays::GRID) {
+            overlays::draw_grid(screen, tile_map, camera);
+        }
+        if self.overlays.contains(Overlays::SCREENS) {
+            overlays::draw_screens(screen, tile_map, camera);
+        }
+        if self.overlays.contains(Overlays::FLAGS) {
+            overlays::draw_flags(screen, tile_map, tile_set, camera, self.preview_mask);
+        }
+
+        // Checked again after the level changed, not while painting
+        let reachable = self.overlays.contains(Overlays::REACHABLE);
+        if reachable
+            && !input_state.is_mouse_down(MouseButton::Left)
+            && self
+                .reachability
+                .as_ref()
+                .is_none_or(|(tiles, _)| *tiles != tile_map.tiles)
+        {
+            let reach = MASK_STATES
+                .iter()
+                .map(|&(mask, _)| reachability::reach(tile_map, tile_set, mask, &self.spawn))
+                .collect();
+            self.reachability = Some((tile_map.tiles.clone(), reach));
+        }
+        let preview_state = MASK_STATES
+            .iter()
+            .position(|&(mask, _)| mask == self.preview_mask)
+            .unwrap_or_default();
+        if reachable && let Some((_, reach)) = &self.reachability {
+            overlays::draw_reachable(screen, tile_map, &reach[preview_state], camera);
+        }
+
         match self.editor_mode {
             EditorMode::TileMode => {
-                if input_state.is_key_pressed(Key::LeftBracket) && self.selected_tile > 0 {
-                    self.selected_tile -= 1;
+                if input_state.is_key_pressed(Key::LeftBracket) {
+                    self.selected_tile = self.palette.step(tile_set, self.selected_tile, false);
                 }
-                if input_state.is_key_pressed(Key::RightBracket)
-                    && self.selected_tile < (tile_set.tiles.len() - 1) as u32
-                {
-                    self.selected_tile += 1;
+                if input_state.is_key_pressed(Key::RightBracket) {
+                    self.selected_tile = self.palette.step(tile_set, self.selected_tile, true);
+                }
+                if shortcuts {
+                    self.palette.handle_keys(input_state, self.selected_tile);
                 }
 
-                if input_state.mouse.y < 192.0 {
+                if !self.ui.wants_mouse() {
                     if input_state.is_mouse_down(MouseButton::Left) {
                         let mouse_ws = screen_to_world_space(input_state.mouse, camera);
                         let mouse_ws = mouse_ws.as_uvec2();
@@ -194,32 +623,16 @@ impl EditorState {
                         }
                     }
                 }
-
-                screen.draw_rectangle(0, 192, 255, 207, true, 0x0);
-                screen.draw_rectangle(0, 192, 255, 207, false, 0xffffffff);
-
-                for (i, tile) in tile_set.tiles.iter().take(24).enumerate() {
-                    let aabb = Aabb {
-                        min: vec2(3.0 + i as f32 * 10.0, 192.0 + 3.0),
-                        max: vec2(12.0 + i as f32 * 10.0, 192.0 + 12.0),
-                    };
-                    if i == self.selected_tile as usize {
-                        draw_aabb_ss(screen, &aabb, 0xffffff);
-                    }
-
-                    if input_state.is_mouse_down(MouseButton::Left)
-                        && aabb.point_intersects(input_state.mouse)
-                    {
-                        self.selected_tile = i as u32;
-                    }
-                    tile.draw_on(screen, 4 + i as i32 * 10, 192 + 4);
-                }
             }
             EditorMode::ObjectMode => {
-                if input_state.is_key_pressed(Key::LeftBracket) && self.selected_object > 0 {
+                if shortcuts
+                    && input_state.is_key_pressed(Key::LeftBracket)
+                    && self.selected_object > 0
+                {
                     self.selected_object -= 1;
                 }
-                if input_state.is_key_pressed(Key::RightBracket)
+                if shortcuts
+                    && input_state.is_key_pressed(Key::RightBracket)
                     && self.selected_object < (self.object_buttons.len() - 1) as u32
                 {
                     self.selected_object += 1;
@@ -227,30 +640,32 @@ impl EditorState {
 
                 // Draw object spawn list
                 for object in self.object_spawns.iter() {
-                    // TODO(manon): Linear search for every object *PUKE*
-                    if let Some(button) = self
-                        .object_buttons
-                        .iter()
-                        .find(|button| button.object_type == object.object_type)
-                    {
-                        let position = world_space_to_screen_space(object.position, camera);
-
-                        button.icon_bitmap.draw_on_scaled(
-                            screen,
-                            position.x as i32,
-                            position.y as i32,
-                            camera.zoom,
-                            camera.zoom,
-                        );
+                    self.draw_object(screen, camera, object.object_type, object.position);
+                }
+
+                // Highlight the inspected object and where it patrols
+                if let Some(spawn) = self.inspected_object.map(|i| &self.object_spawns[i]) {
+                    draw_aabb_ws(
+                        screen,
+                        &spawn.aabb.translate(spawn.position),
+                        camera,
+                        0xdcaf00,
+                    );
+                    if let Some((left, right)) = spawn.properties.patrol {
+                        let bounds = Aabb {
+                            min: spawn.position + vec2(spawn.aabb.min.x - left, spawn.aabb.max.y),
+                            max: spawn.position + vec2(spawn.aabb.max.x + right, spawn.aabb.max.y),
+                        };
+                        draw_aabb_ws(screen, &bounds, camera, 0xdcaf00);
                     }
                 }
 
-                let selected_button = &self.object_buttons[self.selected_object as usize];
+                let selected_type = self.object_buttons[self.selected_object as usize].object_type;
                 let mouse_pos_ws = screen_to_world_space(input_state.mouse, camera);
                 let rounded_pos_ws = (mouse_pos_ws / 8.0).floor() * 8.0;
 
                 if input_state.is_mouse_pressed(MouseButton::Right)
-                    && input_state.mouse.y < 184.0
+                    && !self.ui.wants_mouse()
                     && let Some(index_to_remove) = self.object_spawns.iter().position(|object| {
                         object
                             .aabb
@@ -259,10 +674,15 @@ impl EditorState {
                     })
                 {
                     self.object_spawns.remove(index_to_remove);
+                    self.inspected_object = match self.inspected_object {
+                        Some(i) if i == index_to_remove => None,
+                        Some(i) if i > index_to_remove => Some(i - 1),
+                        inspected => inspected,
+                    };
                 }
 
                 if input_state.is_mouse_pressed(MouseButton::Left) {
-                    if input_state.mouse.y < 184.0 {
+                    if !self.ui.wants_mouse() {
                         self.held_object = self.object_spawns.iter().position(|object| {
                             object
                                 .aabb
@@ -275,16 +695,12 @@ impl EditorState {
 
                             self.object_spawns.push(ObjectSpawn {
                                 position: rounded_pos_ws,
-                                aabb: Aabb {
-                                    min: Vec2::ZERO,
-                                    max: vec2(
-                                        (selected_button.icon_bitmap.width - 1) as f32,
-                                        (selected_button.icon_bitmap.height - 1) as f32,
-                                    ),
-                                },
-                                object_type: selected_button.object_type,
+                                aabb: self.spawn_aabb(selected_type),
+                                object_type: selected_type,
+                                properties: ObjectProperties::default(),
                             })
                         }
+                        self.inspected_object = self.held_object;
                     }
                 } else if input_state.is_mouse_down(MouseButton::Left)
                     && let Some(held_object) = self.held_object
@@ -295,34 +711,117 @@ impl EditorState {
                 if input_state.is_mouse_released(MouseButton::Left) {
                     self.held_object = None;
                 }
+            }
+            EditorMode::SelectMode => {
+                self.tick_select_mode(screen, tile_map, tile_set, camera, input_state, shortcuts);
+            }
+        }
 
-                screen.draw_rectangle(0, 184, 255, 207, true, 0x0);
-                screen.draw_rectangle(0, 184, 255, 207, false, 0xffffffff);
-                for (i, button) in self.object_buttons.iter().enumerate() {
-                    let aabb = Aabb {
-                        min: vec2(3.0 + i as f32 * 18.0, 184.0 + 3.0),
-                        max: vec2(20.0 + i as f32 * 18.0, 184.0 + 20.0),
-                    };
+        if self.overlays.contains(Overlays::HITBOXES) {
+            overlays::draw_hitboxes(screen, &self.object_spawns, &self.object_buttons, camera);
+        }
 
-                    button.icon_bitmap.draw_on_scaled(
-                        screen,
-                        4 + i as i32 * 18 + (16 - (button.icon_bitmap.width.min(16) as i32)) / 2,
-                        184 + 4 + (16 - (button.icon_bitmap.height.min(16) as i32)) / 2,
-                        button.icon_scale,
-                        button.icon_scale,
-                    );
+        if self.editor_mode != EditorMode::SelectMode
+            && let Some((block, _)) = self.dragged.take()
+        {
+            // Switched modes while moving a block, drop it where it was
+            let selection = self.selection.expect("A dragged block is selected");
+            block.place(tile_map, &mut self.object_spawns, selection.min);
+        }
 
-                    if i == self.selected_object as usize {
-                        draw_aabb_ss(screen, &aabb, 0xffffff);
-                    }
+        let mut ui = Ui::begin(screen, input_state, font, &mut self.ui, delta_time);
 
-                    if input_state.is_mouse_down(MouseButton::Left)
-                        && aabb.point_intersects(input_state.mouse)
-                    {
+        // Palette or toolbar with the tiles or objects to place
+        match self.editor_mode {
+            EditorMode::TileMode => {
+                if let Some(tile) = self.palette.draw(&mut ui, tile_set, self.selected_tile) {
+                    self.selected_tile = tile;
+                }
+            }
+            EditorMode::ObjectMode => {
+                ui.panel(&rect(0.0, 184.0, 256.0, 24.0));
+                for (i, button) in self.object_buttons.iter().enumerate() {
+                    let aabb = rect(3.0 + i as f32 * 18.0, 184.0 + 3.0, 18.0, 18.0);
+                    let selected = i == self.selected_object as usize;
+                    let id = format!("{:?}", button.object_type);
+                    if ui.image_button(&id, &aabb, &button.icon_bitmap, selected) {
                         self.selected_object = i as u32;
                     }
+                    ui.tooltip(&id);
+                }
+
+                // The same objects by name, scrolled with the mouse wheel
+                let names: Vec<String> = self
+                    .object_buttons
+                    .iter()
+                    .map(|button| button.object_type.name().replace('_', " "))
+                    .collect();
+                let names: Vec<&str> = names.iter().map(String::as_str).collect();
+                let mut selected = self.selected_object as usize;
+                if ui.list(
+                    "object list",
+                    &rect(96.0, 186.0, 100.0, 20.0),
+                    &names,
+                    &mut selected,
+                    &mut self.object_list_first,
+                ) {
+                    self.selected_object = selected as u32;
+                }
+
+                if let Some(index) = self.inspected_object {
+                    inspect(&mut ui, &mut self.object_spawns[index]);
                 }
             }
+            EditorMode::SelectMode => {}
+        }
+
+        // Mode switches and saving
+        ui.panel(&rect(137.0, 0.0, 119.0, 15.0));
+        if ui.button("mode tile", &rect(139.0, 2.0, 28.0, 11.0), "TILE") {
+            self.editor_mode = EditorMode::TileMode;
+        }
+        ui.tooltip("Tile mode (1)");
+        if ui.button("mode object", &rect(168.0, 2.0, 28.0, 11.0), "OBJ") {
+            self.editor_mode = EditorMode::ObjectMode;
+        }
+        ui.tooltip("Object mode (2)");
+        if ui.button("mode select", &rect(197.0, 2.0, 28.0, 11.0), "SEL") {
+            self.editor_mode = EditorMode::SelectMode;
+        }
+        ui.tooltip("Select mode (3)");
+        if ui.button("save", &rect(226.0, 2.0, 28.0, 11.0), "SAVE") {
+            save = true;
+        }
+        ui.tooltip("Save the level (S)");
+
+        // Play-testing
+        ui.panel(&rect(0.0, 0.0, 70.0, 15.0));
+        let masks = [("R", "Red mask"), ("G", "Green mask"), ("B", "Blue mask")];
+        for (i, (label, tooltip)) in masks.into_iter().enumerate() {
+            let button = rect(2.0 + i as f32 * 12.0, 2.0, 12.0, 11.0);
+            ui.toggle_button(tooltip, &button, label, &mut self.playtest_masks[i]);
+            ui.tooltip(tooltip);
+        }
+        if ui.button("play", &rect(39.0, 2.0, 29.0, 11.0), "PLAY") {
+            let center = vec2(ui.screen.width as f32, ui.screen.height as f32) * 0.5;
+            playtest = Some(screen_to_world_space(center, camera));
+        }
+        ui.tooltip("Play from here, P plays from the mouse (not in music mode)");
+
+        overlays::toolbar(&mut ui, &mut self.overlays, &mut self.preview_mask);
+        if reachable && let Some((_, reach)) = &self.reachability {
+            overlays::reach_report(&mut ui, reach, &self.spawn, preview_state);
+        }
+        ui.end();
+
+        if save {
+            tile_map.store_to_file(LEVEL_PATH);
+            self.store_objects(LEVEL_PATH);
+            println!("Level Saved!");
+        }
+
+        if self.overlays.contains(Overlays::COORDINATES) && !self.ui.wants_mouse() {
+            overlays::draw_coordinates(screen, font, tile_map, camera, input_state.mouse);
         }
 
         // Draw mouse cursor
@@ -331,5 +830,7 @@ impl EditorState {
             input_state.mouse.y as i32,
             0xff00ff,
         );
+
+        playtest.map(|position| self.playtest_start(position))
     }
 }
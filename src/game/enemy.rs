@@ -13,9 +13,15 @@ pub struct Enemy {
     pub hitbox: Aabb,     // check player collision
     pub sprite: Sprite,
     pub color_mask: u32,
+    pub speed: f32,
+    pub patrol: Option<(f32, f32)>, // turns around when walking past these x coordinates
+    step_timer: f32,
 }
 
 impl Enemy {
+    const STEP_INTERVAL: f32 = 0.25;
+    pub const DEFAULT_SPEED: f32 = 50.0;
+
     pub fn new(position: Vec2, going_left: bool, sprite: &Sprite, color_mask: u32) -> Self {
         Self {
             position,
@@ -24,12 +30,12 @@ impl Enemy {
                 min: vec2(0.0, 0.0),
                 max: vec2(15.0, 7.0),
             },
-            hitbox: Aabb {
-                min: vec2(1.0, 1.0),
-                max: vec2(14.0, 6.0),
-            },
+            hitbox: sprite.frame().hitbox.clone(),
             sprite: sprite.clone(),
             color_mask: color_mask & 0xffffff,
+            speed: Self::DEFAULT_SPEED,
+            patrol: None,
+            step_timer: 0.0,
         }
     }
 
@@ -37,12 +43,12 @@ impl Enemy {
         self.color_mask != 0xffffff
     }
 
-    pub fn tick(&mut self, delta_time: f32, tile_map: &TileMap, tile_set: &TileSet) {
-        let speed = 50.0;
+    // Returns true when the enemy takes a step, to play its footstep
+    pub fn tick(&mut self, delta_time: f32, tile_map: &TileMap, tile_set: &TileSet) -> bool {
         let delta_x = if self.going_left {
-            -speed * delta_time
+            -self.speed * delta_time
         } else {
-            speed * delta_time
+            self.speed * delta_time
         };
 
         let sample_points = if self.going_left {
@@ -72,7 +78,12 @@ impl Enemy {
             ),
         ];
 
-        if flags.iter().any(|flag| flag.contains(TileFlags::COLLISION)) {
+        let x = self.position.x + delta_x;
+        let leaves_patrol = self.patrol.is_some_and(|(min, max)| {
+            (self.going_left && x < min) || (!self.going_left && x > max)
+        });
+
+        if leaves_patrol || flags.iter().any(|flag| flag.contains(TileFlags::COLLISION)) {
             self.going_left = !self.going_left;
         } else {
             self.position.x += delta_x;
@@ -80,6 +91,14 @@ impl Enemy {
 
         // self.sprite.seconds_per_frame = 1.0 / 4.0;
         self.sprite.tick(delta_time);
+
+        self.step_timer -= delta_time;
+        if self.step_timer < 0.0 {
+            self.step_timer += Self::STEP_INTERVAL;
+            true
+        } else {
+            false
+        }
     }
     pub fn hitbox_aabb_world_space(&self) -> Aabb {
         Aabb {
@@ -0,0 +1,99 @@
+// Panel of the object editor for changing the properties of the selected object
+use crate::game::{
+    editor::{ObjectSpawn, ObjectType},
+    ui::{Ui, rect},
+};
+
+const LEFT: f32 = 156.0;
+const TOP: f32 = 16.0; // below the mode buttons
+const WIDTH: f32 = 100.0;
+const HEIGHT: f32 = 168.0; // down to the object toolbar
+const ROW_HEIGHT: f32 = 13.0;
+
+const MAX_SPEED: f32 = 150.0;
+const MAX_PATROL_DISTANCE: f32 = 256.0;
+
+pub fn inspect(ui: &mut Ui, spawn: &mut ObjectSpawn) {
+    ui.panel(&rect(LEFT, TOP, WIDTH, HEIGHT));
+    let x = LEFT + 3.0;
+    let width = WIDTH - 6.0;
+    let mut y = TOP + 4.0;
+
+    ui.label(
+        x as i32,
+        y as i32,
+        &spawn.object_type.name().replace('_', " "),
+    );
+    y += 9.0;
+    let position = format!("x {} y {}", spawn.position.x, spawn.position.y);
+    ui.label(x as i32, y as i32, &position);
+    y += 10.0;
+
+    // Ids are stored as a single word in the level
+    ui.label(x as i32, y as i32 + 3, "id");
+    let properties = &mut spawn.properties;
+    if ui.text_field(
+        "object id",
+        &rect(x + 14.0, y, width - 14.0, 11.0),
+        &mut properties.id,
+    ) {
+        properties.id.retain(|c| !c.is_whitespace() && c != '#');
+    }
+    y += ROW_HEIGHT;
+
+    if !spawn.object_type.is_hedgehog() {
+        return;
+    }
+
+    let colors = [
+        ("W", "hedgehog white", ObjectType::WhiteHedgehog),
+        ("R", "hedgehog red", ObjectType::RedHedgehog),
+        ("G", "hedgehog green", ObjectType::GreenHedgehog),
+        ("B", "hedgehog blue", ObjectType::BlueHedgehog),
+    ];
+    for (i, (label, id, object_type)) in colors.into_iter().enumerate() {
+        let mut is_type = spawn.object_type == object_type;
+        let button = rect(x + i as f32 * 12.0, y, 12.0, 11.0);
+        if ui.toggle_button(id, &button, label, &mut is_type) {
+            spawn.object_type = object_type;
+        }
+    }
+    ui.toggle_button(
+        "going left",
+        &rect(x + 52.0, y, width - 52.0, 11.0),
+        "LEFT",
+        &mut properties.going_left,
+    );
+    ui.tooltip("Walks to the left first");
+    y += ROW_HEIGHT;
+
+    let slider = rect(x, y, width, 11.0);
+    if ui.slider(
+        "speed",
+        &slider,
+        "speed",
+        &mut properties.speed,
+        0.0,
+        MAX_SPEED,
+    ) {
+        properties.speed = properties.speed.round();
+    }
+    y += ROW_HEIGHT;
+
+    let mut patrols = properties.patrol.is_some();
+    if ui.toggle("patrol", &rect(x, y, width, 11.0), "patrol", &mut patrols) {
+        properties.patrol = patrols.then_some((32.0, 32.0));
+    }
+    ui.tooltip("Turn around at a distance from the spawn");
+    y += ROW_HEIGHT;
+
+    if let Some((left, right)) = &mut properties.patrol {
+        for (label, distance) in [("left", left), ("right", right)] {
+            let slider = rect(x, y, width, 11.0);
+            if ui.slider(label, &slider, label, distance, 0.0, MAX_PATROL_DISTANCE) {
+                *distance = (*distance / 8.0).round() * 8.0;
+            }
+            y += ROW_HEIGHT;
+        }
+    }
+}
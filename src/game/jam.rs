@@ -0,0 +1,289 @@
+use crate::audio::Audio;
+use crate::audio::notes::midi_note_name;
+use crate::audio::sound::WAVES;
+use crate::audio::synth::{SYNTH_ENVELOPE, SYNTH_VOLUME, SynthEvent};
+use crate::bitmap::{self, Bitmap, Font};
+use crate::game::Key;
+
+const PIANO_KEYS: [Key; 17] = [
+    Key::MusicC3,
+    Key::MusicCs3,
+    Key::MusicD3,
+    Key::MusicDs3,
+    Key::MusicE3,
+    Key::MusicF3,
+    Key::MusicFs3,
+    Key::MusicG3,
+    Key::MusicGs3,
+    Key::MusicA3,
+    Key::MusicAs3,
+    Key::MusicB3,
+    Key::MusicC4,
+    Key::MusicCs4,
+    Key::MusicD4,
+    Key::MusicDs4,
+    Key::MusicE4,
+];
+const LOWEST_NOTE: i32 = 48; // C3
+
+const BAR_LENGTH: usize = 4;
+const LOOP_BARS: usize = 4;
+const STEPS_PER_BEAT: usize = 4;
+const LOOP_STEPS: usize = LOOP_BARS * BAR_LENGTH * STEPS_PER_BEAT;
+
+// Tempo when no music is playing to follow, in beats per second
+const DEFAULT_TEMPO: f64 = 2.0;
+
+// Outside of assets/music, every song in there is loaded on start. Move it in to keep it
+const EXPORT_PATH: &str = "jam.txt";
+
+#[derive(Debug, Clone, Copy)]
+struct RecordedNote {
+    note: i32,
+    start: usize,          // step in the loop
+    length: Option<usize>, // in steps, None while the key is still held
+}
+
+// Music mode, play the synth with the keyboard and record a loop on top of the music
+pub struct Jam {
+    octave: i32,
+    wave: usize,
+    sustain: bool,
+    held: [Option<i32>; 17], // note played by each piano key, octave changes don't cut them off
+
+    recording: bool,
+    beat: f64, // position in the loop
+    tempo: f64,
+    notes: Vec<RecordedNote>,
+}
+
+impl Jam {
+    pub fn new() -> Self {
+        Self {
+            octave: 0,
+            wave: 0,
+            sustain: false,
+            held: [None; 17],
+
+            recording: false,
+            beat: 0.0,
+            tempo: DEFAULT_TEMPO,
+            notes: Vec::new(),
+        }
+    }
+
+    fn step(&self) -> usize {
+        (self.beat * STEPS_PER_BEAT as f64) as usize % LOOP_STEPS
+    }
+
+    pub fn on_key(&mut self, key: Key, down: bool, audio: &Audio) {
+        let send = |event| audio.send_synth(event);
+
+        if let Some(i) = PIANO_KEYS.iter().position(|&k| k == key) {
+            if down {
+                let note = LOWEST_NOTE + i as i32 + self.octave * 12;
+                self.held[i] = Some(note);
+                send(SynthEvent::NoteOn(note));
+
+                if self.recording {
+                    // Snap to the closest step so slightly early notes land on the beat
+                    let start = (self.beat * STEPS_PER_BEAT as f64).round() as usize % LOOP_STEPS;
+                    self.notes.push(RecordedNote {
+                        note,
+                        start,
+                        length: None,
+                    });
+                }
+            } else if let Some(note) = self.held[i].take() {
+                send(SynthEvent::NoteOff(note));
+                self.finish_note(note);
+            }
+            return;
+        }
+
+        if !down {
+            if key == Key::MusicSustain {
+                self.sustain = false;
+                send(SynthEvent::Sustain(false));
+            }
+            return;
+        }
+
+        match key {
+            Key::MusicOctaveDown => self.octave = (self.octave - 1).max(-2),
+            Key::MusicOctaveUp => self.octave = (self.octave + 1).min(2),
+            Key::MusicWave => {
+                self.wave = (self.wave + 1) % WAVES.len();
+                send(SynthEvent::Wave(self.wave));
+            }
+            Key::MusicSustain => {
+                self.sustain = true;
+                send(SynthEvent::Sustain(true));
+            }
+            Key::MusicRecord => self.recording = !self.recording,
+            Key::MusicClear => self.notes.clear(),
+            Key::MusicExport if self.notes.is_empty() => println!("Nothing recorded to export"),
+            Key::MusicExport => match std::fs::write(EXPORT_PATH, self.export()) {
+                Ok(()) => println!("Jam exported to {}", EXPORT_PATH),
+                Err(err) => println!("Failed to export jam: \"{}\". Error: {}", EXPORT_PATH, err),
+            },
+            _ => {}
+        }
+    }
+
+    fn finish_note(&mut self, note: i32) {
+        let step = self.step();
+        if let Some(recorded) = self
+            .notes
+            .iter_mut()
+            .find(|n| n.note == note && n.length.is_none())
+        {
+            recorded.length = Some(((step + LOOP_STEPS - recorded.start) % LOOP_STEPS).max(1));
+        }
+    }
+
+    // Releases everything, when leaving music mode
+    pub fn stop(&mut self, audio: &Audio) {
+        for note in self.held.iter_mut().filter_map(|n| n.take()) {
+            audio.send_synth(SynthEvent::NoteOff(note));
+            if let Some(recorded) = self
+                .notes
+                .iter_mut()
+                .find(|n| n.note == note && n.length.is_none())
+            {
+                recorded.length = Some(1);
+            }
+        }
+        for recorded in self.notes.iter() {
+            audio.send_synth(SynthEvent::NoteOff(recorded.note));
+        }
+        self.sustain = false;
+        self.recording = false;
+        audio.send_synth(SynthEvent::Sustain(false));
+    }
+
+    // Follows the beat of the music when it plays, and plays back the recorded loop
+    pub fn tick(&mut self, delta_time: f32, audio: &Audio) {
+        let previous_step = self.step();
+
+        let position = audio.beat_position();
+        if position.playing {
+            self.tempo = position.tempo;
+            self.beat = position.beat % (LOOP_BARS * BAR_LENGTH) as f64;
+        } else {
+            self.beat =
+                (self.beat + delta_time as f64 * self.tempo) % (LOOP_BARS * BAR_LENGTH) as f64;
+        }
+
+        let step = self.step();
+        if step == previous_step {
+            return;
+        }
+
+        // Every step that passed since the last frame, wrapping around the loop
+        let steps_passed = (step + LOOP_STEPS - previous_step) % LOOP_STEPS;
+        for offset in 1..=steps_passed {
+            let current = (previous_step + offset) % LOOP_STEPS;
+            for recorded in self.notes.iter() {
+                let Some(length) = recorded.length else {
+                    continue;
+                };
+                if (recorded.start + length) % LOOP_STEPS == current {
+                    audio.send_synth(SynthEvent::NoteOff(recorded.note));
+                }
+                if recorded.start == current {
+                    audio.send_synth(SynthEvent::NoteOn(recorded.note));
+                }
+            }
+        }
+    }
+
+    pub fn draw(&self, screen: &mut Bitmap, font: &Font) {
+        let status = format!(
+            "jam {} oct {:+}{}",
+            WAVES[self.wave].0,
+            self.octave,
+            if self.sustain { " sus" } else { "" }
+        );
+        screen.draw_str(font, &status, 4, 4, bitmap::YELLOW);
+
+        let step = self.step();
+        let bar_beat = format!(
+            "{}.{} {} notes",
+            step / (BAR_LENGTH * STEPS_PER_BEAT) + 1,
+            step / STEPS_PER_BEAT % BAR_LENGTH + 1,
+            self.notes.len()
+        );
+        let color = if self.recording {
+            bitmap::RED
+        } else {
+            bitmap::WHITE
+        };
+        screen.draw_str(font, &bar_beat, 4, 14, color);
+        if self.recording {
+            screen.draw_str(font, "rec", 4, 24, bitmap::RED);
+        }
+    }
+
+    // The loop as a song in the music file format, overlapping notes go to separate channels
+    fn export(&self) -> String {
+        let mut channels: Vec<[Option<i32>; LOOP_STEPS]> = Vec::new();
+        for recorded in self.notes.iter() {
+            let length = recorded.length.unwrap_or(1);
+            let steps = (0..length)
+                .map(|i| (recorded.start + i) % LOOP_STEPS)
+                .collect::<Vec<_>>();
+
+            // Notes of the same pitch right after each other would be tied together
+            let previous = (recorded.start + LOOP_STEPS - 1) % LOOP_STEPS;
+            let fits = |channel: &[Option<i32>; LOOP_STEPS]| {
+                steps.iter().all(|&s| channel[s].is_none())
+                    && channel[previous] != Some(recorded.note)
+            };
+
+            let index = match channels.iter().position(fits) {
+                Some(index) => index,
+                None => {
+                    channels.push([None; LOOP_STEPS]);
+                    channels.len() - 1
+                }
+            };
+            for s in steps {
+                channels[index][s] = Some(recorded.note);
+            }
+        }
+
+        let mut text = String::from("# Recorded in music mode\n\n");
+        text += &format!("tempo {}\n", (self.tempo * 60.0).round());
+        text += &format!("bar {}\n", BAR_LENGTH);
+        text += &format!("loop 0 {}\n\n", LOOP_BARS);
+        text += &format!(
+            "instrument jam wave={} volume={} attack={} decay={} sustain={} release={}\n",
+            WAVES[self.wave].0,
+            SYNTH_VOLUME,
+            SYNTH_ENVELOPE.attack,
+            SYNTH_ENVELOPE.decay,
+            SYNTH_ENVELOPE.sustain,
+            SYNTH_ENVELOPE.release
+        );
+
+        for (i, channel) in channels.iter().enumerate() {
+            text += &format!("\npattern voice{} {}\n", i, LOOP_BARS);
+            for bar in channel.chunks(BAR_LENGTH * STEPS_PER_BEAT) {
+                let line = bar
+                    .iter()
+                    .map(|note| note.map_or("--".to_owned(), midi_note_name))
+                    .map(|name| format!("{:<4}", name))
+                    .collect::<String>();
+                text += line.trim_end();
+                text += "\n";
+            }
+        }
+
+        text += "\n";
+        for i in 0..channels.len() {
+            text += &format!("channel voice{} jam voice{}\n", i, i);
+        }
+        text
+    }
+}
@@ -0,0 +1,267 @@
+// Helpers drawn over the level in the editor, each can be switched on in the view toolbar
+use crate::{
+    bitmap::{self, Align, Bitmap, Font, TextStyle},
+    game::{
+        Aabb,
+        camera::{Camera, screen_to_world_space, world_space_to_screen_space},
+        draw_aabb_ws,
+        editor::{ObjectButton, ObjectSpawn},
+        palette::{COLLISION_MARK_COLOR, SPIKE_MARK_COLOR},
+        reachability::{MASK_STATES, Reach, Spawn},
+        tilemap::{TileFlags, TileMap, TileSet},
+        ui::{Ui, rect},
+    },
+};
+use bitflags::bitflags;
+use glam::*;
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct Overlays: u32 {
+        const GRID = 0x1;
+        const SCREENS = 0x2; // what fits on screen, starting at the top left of the level
+        const FLAGS = 0x4; // collision and spikes
+        const HITBOXES = 0x8;
+        const COORDINATES = 0x10;
+        const REACHABLE = 0x20; // from the spawn, with the previewed masks
+    }
+}
+
+const GRID_COLOR: u32 = 0x303030;
+const SCREEN_COLOR: u32 = 0x4060ff;
+const HITBOX_COLOR: u32 = 0x00ffff;
+const COORDINATES_COLOR: u32 = 0xffff00;
+const REACHABLE_COLOR: u32 = 0x40ff40;
+
+const TOP: f32 = 16.0; // below the play-test buttons
+const BUTTON_SIZE: f32 = 11.0;
+
+// Vertical toolbar on the left, the last button steps through the masks to preview
+pub fn toolbar(ui: &mut Ui, overlays: &mut Overlays, preview_mask: &mut bitmap::ColorChannel) {
+    let buttons = [
+        ("#", Overlays::GRID, "Grid"),
+        ("S", Overlays::SCREENS, "Screens"),
+        ("F", Overlays::FLAGS, "Collision and spikes"),
+        ("H", Overlays::HITBOXES, "Object hitboxes"),
+        ("XY", Overlays::COORDINATES, "Mouse coordinates"),
+        ("^", Overlays::REACHABLE, "Reachable from the spawn"),
+    ];
+    let height = (buttons.len() + 1) as f32 * BUTTON_SIZE + 4.0;
+    ui.panel(&rect(0.0, TOP, BUTTON_SIZE + 4.0, height));
+
+    let mut y = TOP + 2.0;
+    for (label, overlay, tooltip) in buttons {
+        let mut enabled = overlays.contains(overlay);
+        if ui.toggle_button(
+            tooltip,
+            &rect(2.0, y, BUTTON_SIZE, BUTTON_SIZE),
+            label,
+            &mut enabled,
+        ) {
+            overlays.set(overlay, enabled);
+        }
+        ui.tooltip(tooltip);
+        y += BUTTON_SIZE;
+    }
+
+    let state = MASK_STATES
+        .iter()
+        .position(|&(mask, _)| mask == *preview_mask)
+        .unwrap_or_default();
+    if ui.button(
+        "preview mask",
+        &rect(2.0, y, BUTTON_SIZE, BUTTON_SIZE),
+        MASK_STATES[state].1,
+    ) {
+        *preview_mask = MASK_STATES[(state + 1) % MASK_STATES.len()].0;
+    }
+    ui.tooltip("Masks to preview, A is all of them");
+}
+
+// Where the player can stand under every mask and which masks they can pick up,
+// next to the toolbar
+pub fn reach_report(ui: &mut Ui, reach: &[Reach], spawn: &Spawn, preview_state: usize) {
+    let row_height = 9.0;
+    let height = MASK_STATES.len() as f32 * row_height + 14.0;
+    ui.panel(&rect(BUTTON_SIZE + 5.0, TOP, 80.0, height));
+    let x = BUTTON_SIZE as i32 + 8;
+    ui.label(x, TOP as i32 + 3, "MASKS SPOTS GETS");
+
+    for (i, ((_, name), reach)) in MASK_STATES.iter().zip(reach).enumerate() {
+        let pickups = spawn
+            .pickups
+            .iter()
+            .zip(&reach.pickups)
+            .map(|(&(color, _), &reached)| match color {
+                _ if !reached => '-',
+                bitmap::RED => 'R',
+                bitmap::GREEN => 'G',
+                bitmap::BLUE => 'B',
+                _ => '*',
+            })
+            .collect::<String>();
+        let marker = if i == preview_state { ">" } else { " " };
+        let y = TOP as i32 + 12 + i as i32 * row_height as i32;
+        ui.label(x, y, &format!("{}{}", marker, name));
+        ui.label(x + 18, y, &reach.spots.len().to_string());
+        ui.label(x + 50, y, &pickups);
+    }
+    ui.tooltip("Pickups touched, * is the golden mask");
+}
+
+// First and one past the last tile on screen, inside the level
+fn visible_tiles(screen: &Bitmap, tile_map: &TileMap, camera: &Camera) -> (IVec2, IVec2) {
+    let tile_size = tile_map.tile_size as f32;
+    let screen_size = vec2(screen.width as f32, screen.height as f32);
+    let min = (camera.position / tile_size).floor().as_ivec2();
+    let max = (screen_to_world_space(screen_size, camera) / tile_size)
+        .ceil()
+        .as_ivec2();
+    let level_size = ivec2(tile_map.width as i32, tile_map.height as i32);
+    (
+        min.clamp(IVec2::ZERO, level_size),
+        max.clamp(IVec2::ZERO, level_size),
+    )
+}
+
+pub fn draw_grid(screen: &mut Bitmap, tile_map: &TileMap, camera: &Camera) {
+    let (min, max) = visible_tiles(screen, tile_map, camera);
+    let tile_size = tile_map.tile_size as f32;
+
+    // Zoomed out the lines would cover the tiles, only every 8th is drawn then
+    let step = if tile_size * camera.zoom < 4.0 { 8 } else { 1 };
+
+    let top_left = world_space_to_screen_space(min.as_vec2() * tile_size, camera);
+    let bottom_right = world_space_to_screen_space(max.as_vec2() * tile_size, camera);
+    for x in (min.x..=max.x).filter(|x| x % step == 0) {
+        let x = world_space_to_screen_space(vec2(x as f32 * tile_size, 0.0), camera).x;
+        screen.draw_line(x, top_left.y, x, bottom_right.y, GRID_COLOR);
+    }
+    for y in (min.y..=max.y).filter(|y| y % step == 0) {
+        let y = world_space_to_screen_space(vec2(0.0, y as f32 * tile_size), camera).y;
+        screen.draw_line(top_left.x, y, bottom_right.x, y, GRID_COLOR);
+    }
+}
+
+pub fn draw_screens(screen: &mut Bitmap, tile_map: &TileMap, camera: &Camera) {
+    let screen_size = vec2(screen.width as f32, screen.height as f32);
+    let level_size = uvec2(tile_map.width, tile_map.height).as_vec2() * tile_map.tile_size as f32;
+    let count = (level_size / screen_size).ceil().as_ivec2();
+    for y in 0..count.y {
+        for x in 0..count.x {
+            let min = ivec2(x, y).as_vec2() * screen_size;
+            let aabb = Aabb {
+                min,
+                max: min + screen_size,
+            };
+            draw_aabb_ws(screen, &aabb, camera, SCREEN_COLOR);
+        }
+    }
+}
+
+// Outlines solid tiles in the colors of the palette marks, spikes win over collision
+pub fn draw_flags(
+    screen: &mut Bitmap,
+    tile_map: &TileMap,
+    tile_set: &TileSet,
+    camera: &Camera,
+    color_mask: bitmap::ColorChannel,
+) {
+    let (min, max) = visible_tiles(screen, tile_map, camera);
+    let tile_size = tile_map.tile_size as f32;
+    for y in min.y..max.y {
+        for x in min.x..max.x {
+            let tile = tile_map.tiles[(x + y * tile_map.width as i32) as usize];
+            if tile == 0 || tile_set.is_hidden(tile, color_mask) {
+                continue;
+            }
+            let flags = tile_set.tile_types[(tile - 1) as usize];
+            let color = if flags.contains(TileFlags::SPIKE) {
+                SPIKE_MARK_COLOR
+            } else if flags.contains(TileFlags::COLLISION) {
+                COLLISION_MARK_COLOR
+            } else {
+                continue;
+            };
+
+            let min = world_space_to_screen_space(ivec2(x, y).as_vec2() * tile_size, camera);
+            let max = min + (tile_size * camera.zoom).max(1.0) - 1.0;
+            screen.draw_rectangle(
+                min.x.floor() as i32,
+                min.y.floor() as i32,
+                max.x.floor() as i32,
+                max.y.floor() as i32,
+                false,
+                color,
+            );
+        }
+    }
+}
+
+// A line on the floor of every spot the player can stand on
+pub fn draw_reachable(screen: &mut Bitmap, tile_map: &TileMap, reach: &Reach, camera: &Camera) {
+    let tile_size = tile_map.tile_size as f32;
+    for spot in &reach.spots {
+        let min = world_space_to_screen_space((*spot + IVec2::Y).as_vec2() * tile_size, camera);
+        let width = (tile_size * camera.zoom).max(1.0);
+        screen.draw_line(
+            min.x,
+            min.y - 1.0,
+            min.x + width - 1.0,
+            min.y - 1.0,
+            REACHABLE_COLOR,
+        );
+    }
+}
+
+pub fn draw_hitboxes(
+    screen: &mut Bitmap,
+    object_spawns: &[ObjectSpawn],
+    object_buttons: &[ObjectButton],
+    camera: &Camera,
+) {
+    for spawn in object_spawns {
+        if let Some(button) = object_buttons
+            .iter()
+            .find(|button| button.object_type == spawn.object_type)
+        {
+            let hitbox = button.hitbox.translate(spawn.position);
+            draw_aabb_ws(screen, &hitbox, camera, HITBOX_COLOR);
+        }
+    }
+}
+
+// World position and tile under the mouse, next to the cursor
+pub fn draw_coordinates(
+    screen: &mut Bitmap,
+    font: &Font,
+    tile_map: &TileMap,
+    camera: &Camera,
+    mouse: Vec2,
+) {
+    let position = screen_to_world_space(mouse, camera).floor();
+    let tile = (position / tile_map.tile_size as f32).floor();
+    let text = format!("{} {}  tile {} {}", position.x, position.y, tile.x, tile.y);
+
+    // Flips to the other side of the cursor near the edges of the screen
+    let size = font.measure(&text, None);
+    let right = mouse.x as i32 + 6 + size.x < screen.width as i32;
+    let below = mouse.y as i32 + 8 + size.y < screen.height as i32;
+    let style = TextStyle {
+        color: COORDINATES_COLOR | 0xff000000,
+        shadow: Some(bitmap::BLACK),
+        align: if right { Align::Left } else { Align::Right },
+        ..Default::default()
+    };
+    let x = if right {
+        mouse.x as i32 + 6
+    } else {
+        mouse.x as i32 - 4
+    };
+    let y = if below {
+        mouse.y as i32 + 8
+    } else {
+        mouse.y as i32 - 4 - size.y
+    };
+    screen.draw_text(font, &text, x, y, &style);
+}
@@ -0,0 +1,242 @@
+// Resizable window of the tile editor showing every tile of the tile set. Tiles can be
+// filtered on their flags and marked as favourites, favourites are kept in the config file
+use crate::config::{CONFIG_PATH, Config};
+use crate::game::{
+    InputState, Key,
+    tilemap::{TileFlags, TileSet},
+    ui::{Ui, rect},
+};
+use glam::*;
+
+const CELL_SIZE: f32 = 10.0;
+const TOP: f32 = 16.0; // below the mode buttons
+const HEADER_HEIGHT: f32 = 14.0;
+const HANDLE_WIDTH: f32 = 3.0;
+const MIN_COLUMNS: usize = 6; // room for the filter buttons
+const MAX_COLUMNS: usize = 24;
+
+pub(crate) const COLLISION_MARK_COLOR: u32 = 0xffffff;
+pub(crate) const SPIKE_MARK_COLOR: u32 = 0xff00ff;
+const FAVOURITE_MARK_COLOR: u32 = 0xdcaf00;
+
+pub struct TilePalette {
+    width: f32,
+    first_row: usize,
+    filter: TileFlags, // shown tiles have all of these flags
+    favourites_only: bool,
+    favourites: Vec<u32>,
+    hovered: Option<u32>,
+}
+
+impl TilePalette {
+    pub fn new() -> Self {
+        let favourites = Config::from_file(CONFIG_PATH)
+            .get::<String>("editor_favourite_tiles")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|tile| tile.trim().parse().ok())
+            .collect();
+
+        Self {
+            width: Self::width_for(8),
+            first_row: 0,
+            filter: TileFlags::empty(),
+            favourites_only: false,
+            favourites,
+            hovered: None,
+        }
+    }
+
+    fn width_for(columns: usize) -> f32 {
+        columns as f32 * CELL_SIZE + HANDLE_WIDTH + 7.0
+    }
+
+    fn columns(&self) -> usize {
+        (((self.width - HANDLE_WIDTH - 7.0) / CELL_SIZE) as usize).clamp(MIN_COLUMNS, MAX_COLUMNS)
+    }
+
+    fn is_shown(&self, tile_set: &TileSet, tile: usize) -> bool {
+        tile_set.tile_types[tile].contains(self.filter)
+            && (!self.favourites_only || self.favourites.contains(&(tile as u32)))
+    }
+
+    // Next shown tile before or after `tile`, `[` and `]` skip over the hidden ones
+    pub fn step(&self, tile_set: &TileSet, tile: u32, forward: bool) -> u32 {
+        let tile = tile as usize;
+        let next = if forward {
+            (tile + 1..tile_set.tiles.len()).find(|&i| self.is_shown(tile_set, i))
+        } else {
+            (0..tile).rev().find(|&i| self.is_shown(tile_set, i))
+        };
+        next.unwrap_or(tile) as u32
+    }
+
+    fn toggle_favourite(&mut self, tile: u32) {
+        if let Some(index) = self.favourites.iter().position(|&t| t == tile) {
+            self.favourites.remove(index);
+        } else {
+            self.favourites.push(tile);
+            self.favourites.sort();
+        }
+
+        let favourites = self
+            .favourites
+            .iter()
+            .map(|tile| tile.to_string())
+            .collect::<Vec<_>>();
+        let mut config = Config::from_file(CONFIG_PATH);
+        config.set("editor_favourite_tiles", favourites.join(","));
+        config.store_to_file(CONFIG_PATH);
+    }
+
+    // C and X filter on collision and spikes, R, G and B on color, V shows only favourites
+    // and F marks the tile under the mouse, or the selected one, as favourite
+    pub fn handle_keys(&mut self, input_state: &InputState, selected_tile: u32) {
+        let filters = [
+            (Key::EditorFilterCollision, TileFlags::COLLISION),
+            (Key::EditorFilterSpike, TileFlags::SPIKE),
+            (Key::MaskRed, TileFlags::RED),
+            (Key::MaskGreen, TileFlags::GREEN),
+            (Key::MaskBlue, TileFlags::BLUE),
+        ];
+        for (key, flag) in filters {
+            if input_state.is_key_pressed(key) {
+                self.filter.toggle(flag);
+                self.first_row = 0;
+            }
+        }
+        if input_state.is_key_pressed(Key::EditorFavourites) {
+            self.favourites_only = !self.favourites_only;
+            self.first_row = 0;
+        }
+        if input_state.is_key_pressed(Key::EditorFavourite) {
+            self.toggle_favourite(self.hovered.unwrap_or(selected_tile));
+        }
+    }
+
+    // Returns the tile that was clicked
+    pub fn draw(&mut self, ui: &mut Ui, tile_set: &TileSet, selected_tile: u32) -> Option<u32> {
+        let screen_size = vec2(ui.screen.width as f32, ui.screen.height as f32);
+        let left = screen_size.x - self.width;
+        ui.panel(&rect(left, TOP, self.width, screen_size.y - TOP));
+
+        // Dragging the left edge resizes the palette
+        let handle = rect(left, TOP, HANDLE_WIDTH, screen_size.y - TOP);
+        self.width = (self.width - ui.drag_handle("palette size", &handle).x)
+            .clamp(Self::width_for(MIN_COLUMNS), Self::width_for(MAX_COLUMNS));
+        ui.tooltip("Drag to resize");
+
+        // Filters
+        let buttons = [
+            ("C", TileFlags::COLLISION, "Collision (C)"),
+            ("X", TileFlags::SPIKE, "Spikes (X)"),
+            ("R", TileFlags::RED, "Red (R)"),
+            ("G", TileFlags::GREEN, "Green (G)"),
+            ("B", TileFlags::BLUE, "Blue (B)"),
+        ];
+        let mut x = left + HANDLE_WIDTH + 2.0;
+        for (label, flag, tooltip) in buttons {
+            let mut enabled = self.filter.contains(flag);
+            if ui.toggle_button(
+                tooltip,
+                &rect(x, TOP + 2.0, CELL_SIZE, 11.0),
+                label,
+                &mut enabled,
+            ) {
+                self.filter.set(flag, enabled);
+                self.first_row = 0;
+            }
+            ui.tooltip(tooltip);
+            x += CELL_SIZE;
+        }
+        let favourites_button = rect(x, TOP + 2.0, CELL_SIZE, 11.0);
+        if ui.toggle_button(
+            "favourites",
+            &favourites_button,
+            "*",
+            &mut self.favourites_only,
+        ) {
+            self.first_row = 0;
+        }
+        ui.tooltip("Favourites (V), F marks a tile");
+
+        // Tiles
+        let shown = (0..tile_set.tiles.len())
+            .filter(|&tile| self.is_shown(tile_set, tile))
+            .collect::<Vec<_>>();
+        let columns = self.columns();
+        let grid = rect(
+            left + HANDLE_WIDTH + 2.0,
+            TOP + HEADER_HEIGHT + 2.0,
+            columns as f32 * CELL_SIZE + 4.0,
+            screen_size.y - TOP - HEADER_HEIGHT - 4.0,
+        );
+        let visible_rows = ((grid.max.y - grid.min.y) / CELL_SIZE) as usize;
+        let rows = shown.len().div_ceil(columns);
+        ui.scroll(&grid, &mut self.first_row, rows, visible_rows);
+
+        if shown.is_empty() {
+            ui.label(grid.min.x as i32 + 2, grid.min.y as i32 + 2, "no tiles");
+        }
+
+        let mut clicked = None;
+        self.hovered = None;
+        for (i, &tile) in shown
+            .iter()
+            .enumerate()
+            .skip(self.first_row * columns)
+            .take(visible_rows * columns)
+        {
+            let row = i / columns - self.first_row;
+            let cell = rect(
+                grid.min.x + (i % columns) as f32 * CELL_SIZE,
+                grid.min.y + row as f32 * CELL_SIZE,
+                CELL_SIZE,
+                CELL_SIZE,
+            );
+            let selected = tile as u32 == selected_tile;
+            if ui.image_button(
+                &format!("tile {}", tile),
+                &cell,
+                &tile_set.tiles[tile],
+                selected,
+            ) {
+                clicked = Some(tile as u32);
+            }
+
+            if ui.hovered() {
+                self.hovered = Some(tile as u32);
+                let flags = tile_set.tile_types[tile]
+                    .iter_names()
+                    .map(|(name, _)| name.to_lowercase())
+                    .collect::<Vec<_>>();
+                ui.tooltip(&format!("#{} {}", tile, flags.join(" ")));
+            }
+
+            // Marks in the corners for the flags, the color and favourites
+            let min = cell.min.as_ivec2();
+            let max = cell.max.as_ivec2() - 1;
+            let flags = tile_set.tile_types[tile];
+            if flags.contains(TileFlags::COLLISION) {
+                ui.screen.plot(min.x + 1, min.y + 1, COLLISION_MARK_COLOR);
+            }
+            if flags.contains(TileFlags::SPIKE) {
+                ui.screen.plot(min.x + 2, min.y + 1, SPIKE_MARK_COLOR);
+            }
+            if flags.intersects(TileFlags::WHITE) {
+                ui.screen.draw_rectangle(
+                    max.x - 2,
+                    max.y - 2,
+                    max.x - 1,
+                    max.y - 1,
+                    true,
+                    tile_set.tile_colors[tile],
+                );
+            }
+            if self.favourites.contains(&(tile as u32)) {
+                ui.screen.plot(max.x - 1, min.y + 1, FAVOURITE_MARK_COLOR);
+            }
+        }
+        clicked
+    }
+}
@@ -0,0 +1,96 @@
+use crate::bitmap::{Bitmap, BlendMode};
+use crate::game::camera::{Camera, world_space_to_screen_space};
+use glam::*;
+
+const PARTICLE_GRAVITY: f32 = 120.0;
+const PARTICLE_LIFETIME: f32 = 0.8;
+const PARTICLE_SPEED: f32 = 70.0;
+
+#[derive(Debug, Clone, Copy)]
+struct Particle {
+    position: Vec2,
+    velocity: Vec2,
+    age: f32,
+    color: u32,
+}
+
+// Sparks that are added on top of the scene, for pickups and savepoints
+pub struct Particles {
+    particles: Vec<Particle>,
+    glow: Bitmap,
+    seed: u32,
+}
+
+impl Particles {
+    pub fn new() -> Self {
+        // Soft white dot, the alpha falls off towards the edge
+        let mut glow = Bitmap::new(5, 5);
+        for y in 0..glow.height {
+            for x in 0..glow.width {
+                let p = vec2(x as f32 - 2.0, y as f32 - 2.0) / 2.5;
+                let alpha = (1.0 - p.length_squared()).clamp(0.0, 1.0);
+                glow.plot(
+                    x as i32,
+                    y as i32,
+                    ((alpha * 255.0) as u32) << 24 | 0xffffff,
+                );
+            }
+        }
+
+        Self {
+            particles: Vec::new(),
+            glow,
+            seed: 1,
+        }
+    }
+
+    fn random(&mut self) -> f32 {
+        // xorshift, good enough to scatter sparks
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed as f32 / u32::MAX as f32
+    }
+
+    pub fn burst(&mut self, position: Vec2, color: u32, count: usize) {
+        for _ in 0..count {
+            let angle = self.random() * std::f32::consts::TAU;
+            let speed = PARTICLE_SPEED * (0.3 + self.random() * 0.7);
+            let age = self.random() * PARTICLE_LIFETIME * 0.3;
+            self.particles.push(Particle {
+                position,
+                velocity: Vec2::from_angle(angle) * speed,
+                age,
+                color,
+            });
+        }
+    }
+
+    pub fn tick(&mut self, delta_time: f32) {
+        for particle in self.particles.iter_mut() {
+            particle.velocity.y += PARTICLE_GRAVITY * delta_time;
+            particle.position += particle.velocity * delta_time;
+            particle.age += delta_time;
+        }
+        self.particles.retain(|p| p.age < PARTICLE_LIFETIME);
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+
+    pub fn draw(&self, screen: &mut Bitmap, camera: &Camera) {
+        let mut spark = self.glow.clone();
+        for particle in self.particles.iter() {
+            // Fading the alpha dims what gets added
+            let fade = ((1.0 - particle.age / PARTICLE_LIFETIME) * 255.0) as u32;
+            for (dst, src) in spark.pixels_mut().iter_mut().zip(self.glow.pixels()) {
+                let alpha = (src >> 24) * fade / 255;
+                *dst = (alpha << 24) | (particle.color & 0xffffff);
+            }
+
+            let pos = world_space_to_screen_space(particle.position, camera) - vec2(2.0, 2.0);
+            spark.draw_on_with(screen, pos.x as i32, pos.y as i32, BlendMode::Additive);
+        }
+    }
+}
@@ -0,0 +1,232 @@
+// Estimates where the player can get to from the spawn under a mask, by simulating walks,
+// falls and jumps with the movement constants of the game. Steering in the air isn't tried,
+// so a good player may get a little further than this
+use crate::{
+    bitmap::{self, ColorChannel},
+    game::{
+        Aabb, GRAVITY, JUMP_IMPULSE, JUMP_SUSTAIN, MOVEMENT_SPEED_X,
+        tilemap::{TileFlags, TileMap, TileSet},
+    },
+};
+use glam::*;
+use std::collections::HashSet;
+
+// Every mask combination, with a short name for the editor
+pub const MASK_STATES: [(ColorChannel, &str); 8] = [
+    (bitmap::BLACK, "-"),
+    (bitmap::RED, "R"),
+    (bitmap::GREEN, "G"),
+    (bitmap::BLUE, "B"),
+    (bitmap::RED | bitmap::GREEN, "RG"),
+    (bitmap::RED | bitmap::BLUE, "RB"),
+    (bitmap::GREEN | bitmap::BLUE, "GB"),
+    (bitmap::WHITE, "A"),
+];
+
+const STEP: f32 = 1.0 / 60.0;
+const MAX_AIR_TIME: f32 = 3.0;
+const MAX_FALL_SPEED: f32 = 242.0; // the speed limit of the player
+const SPEEDS: [f32; 3] = [0.0, 0.5, 1.0]; // horizontal speeds tried, of the top speed
+const JUMP_HOLDS: [f32; 2] = [0.1, MAX_AIR_TIME]; // seconds the jump button is held
+
+// Where the player starts and what they should be able to pick up
+pub struct Spawn {
+    pub position: Vec2,
+    pub hitbox: Aabb,
+    pub pickups: Vec<(ColorChannel, Aabb)>,
+}
+
+pub struct Reach {
+    pub spots: HashSet<IVec2>, // tiles the feet of the player can stand in
+    pub pickups: Vec<bool>,    // touched on the way, in the order of `Spawn::pickups`
+}
+
+struct Simulation<'a> {
+    tile_map: &'a TileMap,
+    tile_set: &'a TileSet,
+    color_mask: ColorChannel,
+    hitbox: &'a Aabb,
+}
+
+impl Simulation<'_> {
+    // Outside of the level is empty, like in the game
+    fn flags(&self, tile: IVec2) -> TileFlags {
+        let size = ivec2(self.tile_map.width as i32, self.tile_map.height as i32);
+        if tile.cmplt(IVec2::ZERO).any() || tile.cmpge(size).any() {
+            return TileFlags::empty();
+        }
+        let index = self.tile_map.tiles[(tile.x + tile.y * size.x) as usize];
+        if index == 0 || self.tile_set.is_hidden(index, self.color_mask) {
+            TileFlags::empty()
+        } else {
+            self.tile_set.tile_types[(index - 1) as usize]
+        }
+    }
+
+    // Flags of every tile the box overlaps, its max corner is inclusive
+    fn touches(&self, aabb: &Aabb) -> TileFlags {
+        let tile_size = self.tile_map.tile_size as f32;
+        let min = (aabb.min / tile_size).floor().as_ivec2();
+        let max = (aabb.max / tile_size).floor().as_ivec2();
+        let mut flags = TileFlags::empty();
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                flags |= self.flags(ivec2(x, y));
+            }
+        }
+        flags
+    }
+
+    fn body(&self, position: Vec2) -> Aabb {
+        self.hitbox.translate(position)
+    }
+
+    // Standing with the left side against the edge of the tile
+    fn position(&self, spot: IVec2) -> Vec2 {
+        let tile_size = self.tile_map.tile_size as f32;
+        vec2(
+            spot.x as f32 * tile_size - self.hitbox.min.x,
+            (spot.y + 1) as f32 * tile_size - 1.0 - self.hitbox.max.y,
+        )
+    }
+
+    fn is_free(&self, spot: IVec2) -> bool {
+        let flags = self.touches(&self.body(self.position(spot)));
+        !flags.intersects(TileFlags::COLLISION | TileFlags::SPIKE)
+    }
+
+    // Spikes below kill too
+    fn can_stand(&self, spot: IVec2) -> bool {
+        let body = self.body(self.position(spot));
+        let feet = Aabb {
+            min: vec2(body.min.x, body.max.y + 1.0),
+            max: vec2(body.max.x, body.max.y + 1.0),
+        };
+        let floor = self.touches(&feet);
+        self.is_free(spot)
+            && floor.contains(TileFlags::COLLISION)
+            && !floor.contains(TileFlags::SPIKE)
+    }
+
+    // Follows the player through the air until they land, `on_path` sees every box on the way
+    fn fly(
+        &self,
+        mut position: Vec2,
+        mut velocity: Vec2,
+        hold: f32,
+        on_path: &mut impl FnMut(&Aabb),
+    ) -> Option<IVec2> {
+        let tile_size = self.tile_map.tile_size as f32;
+        let bottom = (self.tile_map.height as f32 + 1.0) * tile_size;
+        let mut time = 0.0;
+        while time < MAX_AIR_TIME && position.y < bottom {
+            time += STEP;
+            if time < hold && velocity.y < 0.0 {
+                velocity.y -= JUMP_SUSTAIN * STEP;
+            }
+            velocity.y = (velocity.y + GRAVITY * STEP).min(MAX_FALL_SPEED);
+
+            let moved = position + vec2(velocity.x * STEP, 0.0);
+            let flags = self.touches(&self.body(moved));
+            if flags.contains(TileFlags::SPIKE) {
+                return None;
+            }
+            if flags.contains(TileFlags::COLLISION) {
+                velocity.x = 0.0;
+            } else {
+                position = moved;
+            }
+
+            let moved = position + vec2(0.0, velocity.y * STEP);
+            let flags = self.touches(&self.body(moved));
+            if flags.contains(TileFlags::SPIKE) {
+                return None;
+            }
+            if flags.contains(TileFlags::COLLISION) {
+                if velocity.y < 0.0 {
+                    velocity.y = 0.0; // bumped the head
+                    continue;
+                }
+
+                // Landed, snap to the tile the feet are in or the one right of it
+                let body = self.body(position);
+                let spot = (vec2(body.min.x, body.max.y) / tile_size)
+                    .floor()
+                    .as_ivec2();
+                return [spot, spot + IVec2::X]
+                    .into_iter()
+                    .find(|&spot| self.can_stand(spot));
+            }
+            position = moved;
+            on_path(&self.body(position));
+        }
+        None
+    }
+}
+
+pub fn reach(
+    tile_map: &TileMap,
+    tile_set: &TileSet,
+    color_mask: ColorChannel,
+    spawn: &Spawn,
+) -> Reach {
+    let simulation = Simulation {
+        tile_map,
+        tile_set,
+        color_mask,
+        hitbox: &spawn.hitbox,
+    };
+    let mut pickups = vec![false; spawn.pickups.len()];
+    let mut touch = |body: &Aabb| {
+        for (touched, (_, pickup)) in pickups.iter_mut().zip(&spawn.pickups) {
+            *touched |= pickup.overlaps(body);
+        }
+    };
+
+    // The spawn is in the air, the player drops from there
+    let mut spots = HashSet::new();
+    let mut queue = Vec::new();
+    if let Some(spot) = simulation.fly(spawn.position, Vec2::ZERO, 0.0, &mut touch) {
+        spots.insert(spot);
+        queue.push(spot);
+    }
+
+    while let Some(spot) = queue.pop() {
+        let position = simulation.position(spot);
+        touch(&simulation.body(position));
+
+        let mut landings = Vec::new();
+        for direction in [-1, 1] {
+            // Walking, or off the edge
+            let side = spot + ivec2(direction, 0);
+            if simulation.can_stand(side) {
+                landings.push(side);
+            } else if simulation.is_free(side) {
+                for speed in SPEEDS {
+                    let velocity = vec2(direction as f32 * speed * MOVEMENT_SPEED_X, 0.0);
+                    let start = simulation.position(side);
+                    landings.extend(simulation.fly(start, velocity, 0.0, &mut touch));
+                }
+            }
+        }
+        for direction in [-1.0, 0.0, 1.0] {
+            for speed in SPEEDS {
+                if (direction == 0.0) != (speed == 0.0) {
+                    continue;
+                }
+                let velocity = vec2(direction * speed * MOVEMENT_SPEED_X, -JUMP_IMPULSE);
+                for hold in JUMP_HOLDS {
+                    landings.extend(simulation.fly(position, velocity, hold, &mut touch));
+                }
+            }
+        }
+
+        for landing in landings {
+            if spots.insert(landing) {
+                queue.push(landing);
+            }
+        }
+    }
+
+    Reach { spots, pickups }
+}
@@ -0,0 +1,258 @@
+// Rectangular pieces of a level selected in the editor. Blocks can be moved, flipped and
+// rotated, and copied through a clipboard file so they can be pasted into other levels
+use crate::game::{
+    Aabb,
+    editor::{ObjectSpawn, ObjectType, parse_object},
+    tilemap::TileMap,
+};
+use glam::*;
+
+pub(crate) const CLIPBOARD_PATH: &str = "editor_clipboard.txt";
+
+// Tile rectangle of the level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Selection {
+    pub(crate) min: IVec2,
+    pub(crate) size: IVec2,
+}
+
+impl Selection {
+    // Selection spanning both tiles
+    pub(crate) fn between(a: IVec2, b: IVec2) -> Self {
+        Self {
+            min: a.min(b),
+            size: (a - b).abs() + 1,
+        }
+    }
+
+    pub(crate) fn contains(&self, tile: IVec2) -> bool {
+        tile.cmpge(self.min).all() && tile.cmplt(self.min + self.size).all()
+    }
+
+    pub(crate) fn world_aabb(&self, tile_size: u32) -> Aabb {
+        let tile_size = tile_size as f32;
+        Aabb {
+            min: self.min.as_vec2() * tile_size,
+            max: (self.min + self.size).as_vec2() * tile_size,
+        }
+    }
+}
+
+// Tiles and objects taken out of a level, objects are relative to the top left corner
+#[derive(Clone)]
+pub(crate) struct Block {
+    pub(crate) size: IVec2,
+    pub(crate) tiles: Vec<u32>,
+    pub(crate) objects: Vec<ObjectSpawn>,
+}
+
+impl Block {
+    // Tiles outside of the level are empty
+    pub(crate) fn copy(tile_map: &TileMap, objects: &[ObjectSpawn], selection: &Selection) -> Self {
+        let mut tiles = Vec::with_capacity((selection.size.x * selection.size.y) as usize);
+        for y in 0..selection.size.y {
+            for x in 0..selection.size.x {
+                let tile = selection.min + ivec2(x, y);
+                let inside = tile.cmpge(IVec2::ZERO).all()
+                    && tile.x < tile_map.width as i32
+                    && tile.y < tile_map.height as i32;
+                tiles.push(if inside {
+                    tile_map.tiles[(tile.x + tile.y * tile_map.width as i32) as usize]
+                } else {
+                    0
+                });
+            }
+        }
+
+        let area = selection.world_aabb(tile_map.tile_size);
+        let objects = objects
+            .iter()
+            .filter(|object| area.point_intersects(object.position))
+            .map(|object| ObjectSpawn {
+                position: object.position - area.min,
+                ..object.clone()
+            })
+            .collect();
+
+        Self {
+            size: selection.size,
+            tiles,
+            objects,
+        }
+    }
+
+    // Copies the block and empties its area in the level
+    pub(crate) fn cut(
+        tile_map: &mut TileMap,
+        objects: &mut Vec<ObjectSpawn>,
+        selection: &Selection,
+    ) -> Self {
+        let block = Self::copy(tile_map, objects, selection);
+        let empty = Self {
+            size: selection.size,
+            tiles: vec![0; block.tiles.len()],
+            objects: Vec::new(),
+        };
+        empty.place(tile_map, objects, selection.min);
+
+        let area = selection.world_aabb(tile_map.tile_size);
+        objects.retain(|object| !area.point_intersects(object.position));
+        block
+    }
+
+    // Overwrites the level with the block at `min`, tiles outside of the level are dropped
+    pub(crate) fn place(&self, tile_map: &mut TileMap, objects: &mut Vec<ObjectSpawn>, min: IVec2) {
+        for y in 0..self.size.y {
+            for x in 0..self.size.x {
+                let tile = min + ivec2(x, y);
+                if tile.cmpge(IVec2::ZERO).all()
+                    && tile.x < tile_map.width as i32
+                    && tile.y < tile_map.height as i32
+                {
+                    tile_map.tiles[(tile.x + tile.y * tile_map.width as i32) as usize] =
+                        self.tiles[(x + y * self.size.x) as usize];
+                }
+            }
+        }
+
+        let offset = min.as_vec2() * tile_map.tile_size as f32;
+        objects.extend(self.objects.iter().map(|object| ObjectSpawn {
+            position: object.position + offset,
+            ..object.clone()
+        }));
+    }
+
+    fn tile(&self, x: i32, y: i32) -> u32 {
+        self.tiles[(x + y * self.size.x) as usize]
+    }
+
+    // Mirrors left and right, hedgehogs turn around with it
+    pub(crate) fn flip_x(&mut self, tile_size: u32) {
+        self.tiles = (0..self.size.y)
+            .flat_map(|y| (0..self.size.x).map(move |x| (x, y)))
+            .map(|(x, y)| self.tile(self.size.x - 1 - x, y))
+            .collect();
+
+        let width = (self.size.x as u32 * tile_size) as f32;
+        for object in &mut self.objects {
+            object.position.x = width - object.position.x - (object.aabb.max.x + 1.0);
+            object.properties.going_left = !object.properties.going_left;
+            if let Some((left, right)) = object.properties.patrol {
+                object.properties.patrol = Some((right, left));
+            }
+        }
+    }
+
+    pub(crate) fn flip_y(&mut self, tile_size: u32) {
+        self.tiles = (0..self.size.y)
+            .flat_map(|y| (0..self.size.x).map(move |x| (x, y)))
+            .map(|(x, y)| self.tile(x, self.size.y - 1 - y))
+            .collect();
+
+        let height = (self.size.y as u32 * tile_size) as f32;
+        for object in &mut self.objects {
+            object.position.y = height - object.position.y - (object.aabb.max.y + 1.0);
+        }
+    }
+
+    // A quarter turn clockwise, objects keep standing upright
+    pub(crate) fn rotate(&mut self, tile_size: u32) {
+        let size = ivec2(self.size.y, self.size.x);
+        self.tiles = (0..size.y)
+            .flat_map(|y| (0..size.x).map(move |x| (x, y)))
+            .map(|(x, y)| self.tile(y, self.size.y - 1 - x))
+            .collect();
+
+        let height = (self.size.y as u32 * tile_size) as f32;
+        for object in &mut self.objects {
+            object.position = vec2(
+                height - object.position.y - (object.aabb.max.y + 1.0),
+                object.position.x,
+            );
+        }
+        self.size = size;
+    }
+
+    // size <width> <height>, a `row` of tile indices per line, then the objects like in
+    // the level object files
+    pub(crate) fn store_to_file(&self, path: &str) {
+        let mut data = String::from("# Copied in the level editor\n");
+        data += &format!("size {} {}\n", self.size.x, self.size.y);
+        for row in self.tiles.chunks(self.size.x as usize) {
+            let row = row.iter().map(|tile| tile.to_string()).collect::<Vec<_>>();
+            data += &format!("row {}\n", row.join(","));
+        }
+        for object in &self.objects {
+            data += &object.describe(object.position);
+            data.push('\n');
+        }
+        if let Err(err) = std::fs::write(path, data) {
+            println!("Failed to store clipboard: \"{}\". Error: {}", path, err);
+        }
+    }
+
+    // `aabb` gives the size of the objects
+    // The clipboard may come from another level, so tiles past `tile_count` are rejected
+    pub(crate) fn parse(
+        text: &str,
+        tile_count: usize,
+        aabb: impl Fn(ObjectType) -> Aabb,
+    ) -> Result<Self, String> {
+        let mut size = None;
+        let mut tiles = Vec::new();
+        let mut objects = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap_or_default();
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            match words.as_slice() {
+                [] => {}
+                ["size", width, height] => {
+                    let number = |value: &str| {
+                        value.parse::<i32>().map_err(|_| {
+                            format!("line {}: invalid size \"{}\"", line_number, value)
+                        })
+                    };
+                    size = Some(ivec2(number(width)?, number(height)?));
+                }
+                ["row", row] => {
+                    for tile in row.split(',') {
+                        match tile.parse::<u32>() {
+                            Ok(index) if index as usize <= tile_count => tiles.push(index),
+                            _ => {
+                                return Err(format!(
+                                    "line {}: invalid tile \"{}\"",
+                                    line_number, tile
+                                ));
+                            }
+                        }
+                    }
+                }
+                _ => {
+                    let (object_type, position, properties) = parse_object(&words, line_number)?;
+                    objects.push(ObjectSpawn {
+                        position,
+                        aabb: aabb(object_type),
+                        object_type,
+                        properties,
+                    });
+                }
+            }
+        }
+
+        let size = size.ok_or("missing size")?;
+        let count = size
+            .x
+            .checked_mul(size.y)
+            .filter(|_| size.x > 0 && size.y > 0)
+            .ok_or_else(|| format!("invalid size {} {}", size.x, size.y))?;
+        if tiles.len() != count as usize {
+            return Err(format!("expected {} tiles, got {}", count, tiles.len()));
+        }
+        Ok(Self {
+            size,
+            tiles,
+            objects,
+        })
+    }
+}
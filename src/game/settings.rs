@@ -0,0 +1,46 @@
+// Settings menu over the game, for the volume and the language
+use crate::audio::Audio;
+use crate::game::ui::{Ui, rect};
+use crate::strings::Strings;
+
+const LEFT: f32 = 64.0;
+const TOP: f32 = 32.0;
+const WIDTH: f32 = 128.0;
+const HEIGHT: f32 = 120.0;
+
+// `first` is the top visible row of the language list
+pub fn settings(ui: &mut Ui, audio: Option<&mut Audio>, strings: &mut Strings, first: &mut usize) {
+    ui.panel(&rect(LEFT, TOP, WIDTH, HEIGHT));
+    let x = LEFT + 4.0;
+    let width = WIDTH - 8.0;
+    let mut y = TOP + 4.0;
+
+    ui.label(x as i32, y as i32, strings.get("settings"));
+    y += 10.0;
+
+    if let Some(audio) = audio {
+        let mut settings = audio.settings();
+        let sliders = [
+            ("master volume", "volume", &mut settings.master_volume),
+            ("music volume", "music", &mut settings.music_volume),
+            ("sfx volume", "sfx", &mut settings.sfx_volume),
+        ];
+        let mut changed = false;
+        for (id, label, volume) in sliders {
+            let slider = rect(x, y, width, 11.0);
+            changed |= ui.slider(id, &slider, strings.get(label), volume, 0.0, 1.0);
+            y += 13.0;
+        }
+        if changed {
+            audio.set_settings(settings);
+        }
+        y += 1.0;
+    }
+
+    let mut selected = strings.language_index();
+    let names = strings.language_names();
+    let list = rect(x, y, width, TOP + HEIGHT - 4.0 - y);
+    if ui.list("language", &list, &names, &mut selected, first) {
+        strings.set_language(selected);
+    }
+}
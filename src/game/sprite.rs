@@ -1,23 +1,98 @@
+use super::Aabb;
 use crate::bitmap::{self, Bitmap};
 use glam::*;
 
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub bitmap: Bitmap,
+    pub duration: f32,
+    pub pivot: Vec2,           // in pixels of the frame
+    pub hitbox: Aabb,          // relative to the top left of the frame
+    pub event: Option<String>, // reported when the frame is reached
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Playback {
+    Loop,
+    Once, // stops on the last frame
+    PingPong,
+}
+
+impl Playback {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "loop" => Some(Self::Loop),
+            "once" => Some(Self::Once),
+            "pingpong" => Some(Self::PingPong),
+            _ => None,
+        }
+    }
+}
+
+// An animation, usually loaded from an `Atlas`
 #[derive(Debug, Clone)]
 pub struct Sprite {
-    pub frames: Vec<Bitmap>,
+    pub frames: Vec<Frame>,
     pub frame_index: usize,
     pub t: f32,
-    pub seconds_per_frame: f32,
+    pub playback: Playback,
+    pub reversed: bool, // playing backwards, for ping-pong
+    pub finished: bool,
 }
 
 impl Sprite {
-    pub fn tick(&mut self, delta_time: f32) {
+    // Returns the events of the frames that were reached
+    pub fn tick(&mut self, delta_time: f32) -> Vec<String> {
+        let mut events = Vec::new();
+        if self.finished {
+            return events;
+        }
+
         self.t += delta_time;
-        while self.t > self.seconds_per_frame {
-            self.t -= self.seconds_per_frame;
-            self.frame_index = (self.frame_index + 1) % self.frames.len();
+        while self.t > self.frame().duration {
+            self.t -= self.frame().duration;
+
+            let last = self.frames.len() - 1;
+            self.frame_index = match self.playback {
+                Playback::Loop => (self.frame_index + 1) % self.frames.len(),
+                Playback::Once if self.frame_index == last => {
+                    self.finished = true;
+                    break;
+                }
+                Playback::Once => self.frame_index + 1,
+                Playback::PingPong if last == 0 => 0,
+                Playback::PingPong => {
+                    if self.frame_index == last {
+                        self.reversed = true;
+                    } else if self.frame_index == 0 {
+                        self.reversed = false;
+                    }
+                    if self.reversed {
+                        self.frame_index - 1
+                    } else {
+                        self.frame_index + 1
+                    }
+                }
+            };
+            events.extend(self.frame().event.clone());
         }
+        events
+    }
+
+    // Starts over from the first frame, returns its event
+    pub fn restart(&mut self) -> Option<String> {
+        self.frame_index = 0;
+        self.t = 0.0;
+        self.reversed = false;
+        self.finished = false;
+        self.frame().event.clone()
+    }
+
+    pub fn frame(&self) -> &Frame {
+        &self.frames[self.frame_index]
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn draw_colored(
         &self,
         target: &mut Bitmap,
@@ -30,7 +105,7 @@ impl Sprite {
         aura: &Bitmap,
         aura_transl: IVec2,
     ) {
-        let bitmap = &self.frames[self.frame_index];
+        let bitmap = &self.frame().bitmap;
         bitmap.draw_on_scaled_colored_obj(
             target,
             position.x as i32,
@@ -47,31 +122,56 @@ impl Sprite {
     }
 
     pub fn draw_player(&self, target: &mut Bitmap, position: Vec2, scale: Vec2, color_mask: u32) {
-        let components = uvec3(
-            (color_mask >> 16) & 0xff,
-            (color_mask >> 8) & 0xff,
-            color_mask & 0xff,
-        );
-        let color_index = if (color_mask & 0xffffff) == 0 {
-            0
-        } else if color_mask == bitmap::YELLOW {
-            4
-        } else if components.x > components.y {
-            if components.x > components.z { 1 } else { 3 }
-        } else if components.y > components.z {
-            2
-        } else {
-            3
-        };
-
-        let bitmap = &self.frames[self.frame_index];
+        let bitmap = &self.frame().bitmap;
         bitmap.draw_on_scaled_player(
             target,
             position.x as i32,
             position.y as i32,
             scale.x,
             scale.y,
-            color_index,
+            player_color_index(color_mask),
         );
     }
+
+    // Draws the current frame with `pivot`, in pixels of the frame, ending up at `position`.
+    // `transform` rotates, scales and shears around the pivot
+    pub fn draw_player_transformed(
+        &self,
+        target: &mut Bitmap,
+        position: Vec2,
+        pivot: Vec2,
+        transform: Mat2,
+        color_mask: u32,
+    ) {
+        self.frame().bitmap.draw_on_transformed_player(
+            target,
+            sprite_transform(position, pivot, transform),
+            player_color_index(color_mask),
+        );
+    }
+}
+
+pub fn sprite_transform(position: Vec2, pivot: Vec2, transform: Mat2) -> Affine2 {
+    Affine2::from_translation(position)
+        * Affine2::from_mat2(transform)
+        * Affine2::from_translation(-pivot)
+}
+
+fn player_color_index(color_mask: u32) -> u32 {
+    let components = uvec3(
+        (color_mask >> 16) & 0xff,
+        (color_mask >> 8) & 0xff,
+        color_mask & 0xff,
+    );
+    if (color_mask & 0xffffff) == 0 {
+        0
+    } else if color_mask == bitmap::YELLOW {
+        4
+    } else if components.x > components.y {
+        if components.x > components.z { 1 } else { 3 }
+    } else if components.y > components.z {
+        2
+    } else {
+        3
+    }
 }
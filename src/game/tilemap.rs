@@ -33,6 +33,14 @@ pub struct TileSet {
     pub aura_low: Bitmap,
 }
 
+impl TileSet {
+    // Colored tiles are only there when wearing a mask that shares a channel with them
+    pub fn is_hidden(&self, tile: u32, color_mask: bitmap::ColorChannel) -> bool {
+        let index = (tile - 1) as usize;
+        self.tile_types[index].is_colored() && self.tile_colors[index] & color_mask & 0xffffff == 0
+    }
+}
+
 pub struct TileMap {
     pub tile_size: u32,
 
@@ -136,7 +144,14 @@ impl TileMap {
         }
     }
 
-    pub fn editor_draw(&self, tile_set: &TileSet, target: &mut Bitmap, camera: &Camera) {
+    // Only draws the tiles that are there when wearing `color_mask`, white shows all of them
+    pub fn editor_draw(
+        &self,
+        tile_set: &TileSet,
+        target: &mut Bitmap,
+        camera: &Camera,
+        color_mask: bitmap::ColorChannel,
+    ) {
         let tile_size = self.tile_size as f32;
         let draw_tile_size = self.tile_size as f32 * camera.zoom;
 
@@ -175,7 +190,7 @@ impl TileMap {
                 let tile_x = (start_tile_x + x) as u32;
 
                 let tile_id = self.tiles[(tile_y * self.width + tile_x) as usize];
-                if tile_id != 0 {
+                if tile_id != 0 && !tile_set.is_hidden(tile_id, color_mask) {
                     let tile_bmp = &tile_set.tiles[(tile_id - 1) as usize];
                     tile_bmp.draw_on_scaled(target, draw_x, draw_y, camera.zoom, camera.zoom);
                 }
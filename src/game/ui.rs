@@ -0,0 +1,459 @@
+// Immediate mode widgets drawn straight onto a `Bitmap`. The caller describes its widgets
+// every frame and gets back whether they were clicked or changed, the little bit of state
+// that has to survive between frames lives in `UiState`.
+use super::{Aabb, InputState, Key, MouseButton};
+use crate::bitmap::{Align, Bitmap, Font, TextStyle};
+use glam::*;
+
+const PANEL_COLOR: u32 = 0x000000;
+const BORDER_COLOR: u32 = 0xffffff;
+const HOVER_COLOR: u32 = 0x303030;
+const ACTIVE_COLOR: u32 = 0x606060;
+const ACCENT_COLOR: u32 = 0xdcaf00;
+const TEXT_COLOR: u32 = 0xffffff;
+
+// Height of capital letters in the default font, used to center text vertically
+const CAP_HEIGHT: i32 = 5;
+const LIST_ROW_HEIGHT: i32 = 9;
+const TOOLTIP_DELAY: f32 = 0.5;
+const CARET_BLINK_RATE: f32 = 2.0;
+
+// Screen space rectangle from its top left corner and size
+pub fn rect(x: f32, y: f32, width: f32, height: f32) -> Aabb {
+    Aabb {
+        min: vec2(x, y),
+        max: vec2(x + width, y + height),
+    }
+}
+
+// Widgets are told apart by a hash of their id, which has to be unique on screen
+fn widget_id(id: &str) -> u64 {
+    id.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[derive(Default)]
+pub struct UiState {
+    active: Option<u64>, // widget the mouse went down on, it owns the mouse until release
+    focused: Option<u64>, // text field that receives typed characters
+    hovered: Option<u64>,
+    hover_time: f32,
+    time: f32,
+    mouse_over_panel: bool, // the mouse was over a panel last frame
+}
+
+impl UiState {
+    // The mouse is over the UI or dragging a widget, the game should ignore it
+    pub fn wants_mouse(&self) -> bool {
+        self.mouse_over_panel || self.active.is_some()
+    }
+
+    // A text field is being typed in, the game should ignore key shortcuts
+    pub fn wants_keyboard(&self) -> bool {
+        self.focused.is_some()
+    }
+}
+
+struct Interaction {
+    hovered: bool,
+    active: bool,
+    clicked: bool, // released the mouse over the widget it was pressed on
+}
+
+pub struct Ui<'a> {
+    pub screen: &'a mut Bitmap,
+    input: &'a InputState,
+    font: &'a Font,
+    state: &'a mut UiState,
+
+    mouse_over_panel: bool,
+    hovered: Option<u64>,
+    last_widget: Option<u64>,
+    last_hovered: bool,
+    tooltip: Option<String>,
+}
+
+impl<'a> Ui<'a> {
+    pub fn begin(
+        screen: &'a mut Bitmap,
+        input: &'a InputState,
+        font: &'a Font,
+        state: &'a mut UiState,
+        delta_time: f32,
+    ) -> Self {
+        state.time += delta_time;
+        state.hover_time += delta_time;
+        Self {
+            screen,
+            input,
+            font,
+            state,
+            mouse_over_panel: false,
+            hovered: None,
+            last_widget: None,
+            last_hovered: false,
+            tooltip: None,
+        }
+    }
+
+    // Draws the tooltip on top of everything and remembers what the mouse is over
+    pub fn end(self) {
+        if let Some(tooltip) = &self.tooltip {
+            let size = self.font.measure(tooltip, None);
+            let position = (self.input.mouse.as_ivec2() + ivec2(6, 8))
+                .min(ivec2(self.screen.width as i32, self.screen.height as i32) - size - 4)
+                .max(IVec2::ZERO);
+            let max = position + size + ivec2(3, 1);
+            self.screen
+                .draw_rectangle(position.x, position.y, max.x, max.y, true, PANEL_COLOR);
+            self.screen
+                .draw_rectangle(position.x, position.y, max.x, max.y, false, ACCENT_COLOR);
+            self.screen.draw_str(
+                self.font,
+                tooltip,
+                position.x + 2,
+                position.y + 2,
+                TEXT_COLOR,
+            );
+        }
+
+        if self.hovered != self.state.hovered {
+            self.state.hovered = self.hovered;
+            self.state.hover_time = 0.0;
+        }
+
+        // Clicking anywhere but the focused text field takes the focus away
+        if self.input.is_mouse_pressed(MouseButton::Left) && self.state.active != self.state.focused
+        {
+            self.state.focused = None;
+        }
+        if !self.input.is_mouse_down(MouseButton::Left) {
+            self.state.active = None;
+        }
+        self.state.mouse_over_panel = self.mouse_over_panel;
+    }
+
+    fn interact(&mut self, id: u64, rect: &Aabb) -> Interaction {
+        let hovered = rect.point_intersects(self.input.mouse)
+            && self.state.active.is_none_or(|active| active == id);
+        if hovered {
+            self.hovered = Some(id);
+            if self.input.is_mouse_pressed(MouseButton::Left) {
+                self.state.active = Some(id);
+            }
+        }
+        self.last_widget = Some(id);
+        self.last_hovered = hovered;
+
+        let active = self.state.active == Some(id);
+        Interaction {
+            hovered,
+            active,
+            clicked: active && hovered && self.input.is_mouse_released(MouseButton::Left),
+        }
+    }
+
+    fn draw_frame(&mut self, rect: &Aabb, fill: u32, border: u32) {
+        let (min, max) = (rect.min.as_ivec2(), rect.max.as_ivec2() - 1);
+        self.screen
+            .draw_rectangle(min.x, min.y, max.x, max.y, true, fill);
+        self.screen
+            .draw_rectangle(min.x, min.y, max.x, max.y, false, border);
+    }
+
+    fn fill_color(interaction: &Interaction) -> u32 {
+        if interaction.active {
+            ACTIVE_COLOR
+        } else if interaction.hovered {
+            HOVER_COLOR
+        } else {
+            PANEL_COLOR
+        }
+    }
+
+    // Text centered in the rectangle
+    fn draw_centered(&mut self, rect: &Aabb, text: &str, color: u32) {
+        let style = TextStyle {
+            color: color | 0xff000000,
+            align: Align::Center,
+            ..Default::default()
+        };
+        let y = rect.min.y as i32 + (rect.max.y - rect.min.y) as i32 / 2 - CAP_HEIGHT / 2;
+        self.screen
+            .draw_text(self.font, text, rect.center().x as i32, y, &style);
+    }
+
+    // Background for a group of widgets, the mouse over it doesn't reach the game
+    pub fn panel(&mut self, rect: &Aabb) {
+        if rect.point_intersects(self.input.mouse) {
+            self.mouse_over_panel = true;
+        }
+        self.draw_frame(rect, PANEL_COLOR, BORDER_COLOR);
+    }
+
+    pub fn label(&mut self, x: i32, y: i32, text: &str) {
+        self.screen.draw_str(self.font, text, x, y, TEXT_COLOR);
+    }
+
+    // Whether the mouse is over the previous widget
+    pub fn hovered(&self) -> bool {
+        self.last_hovered
+    }
+
+    // Shows the text next to the mouse while it rests on the previous widget
+    pub fn tooltip(&mut self, text: &str) {
+        if self.hovered()
+            && self.last_widget == self.state.hovered
+            && self.state.hover_time > TOOLTIP_DELAY
+        {
+            self.tooltip = Some(text.to_owned());
+        }
+    }
+
+    pub fn button(&mut self, id: &str, rect: &Aabb, label: &str) -> bool {
+        let interaction = self.interact(widget_id(id), rect);
+        self.draw_frame(rect, Self::fill_color(&interaction), BORDER_COLOR);
+        self.draw_centered(rect, label, TEXT_COLOR);
+        interaction.clicked
+    }
+
+    // Button showing a bitmap in its center, selected buttons get a white border
+    pub fn image_button(&mut self, id: &str, rect: &Aabb, icon: &Bitmap, selected: bool) -> bool {
+        let interaction = self.interact(widget_id(id), rect);
+        let border = if selected {
+            BORDER_COLOR
+        } else {
+            Self::fill_color(&interaction)
+        };
+        self.draw_frame(rect, Self::fill_color(&interaction), border);
+
+        let center = rect.center().as_ivec2();
+        icon.draw_on(
+            self.screen,
+            center.x - icon.width as i32 / 2,
+            center.y - icon.height as i32 / 2,
+        );
+        interaction.clicked
+    }
+
+    // Button that stays pressed in while the value is set, returns whether it was flipped
+    pub fn toggle_button(&mut self, id: &str, rect: &Aabb, label: &str, value: &mut bool) -> bool {
+        let interaction = self.interact(widget_id(id), rect);
+        if interaction.clicked {
+            *value = !*value;
+        }
+
+        let (fill, border) = if *value {
+            (ACTIVE_COLOR, ACCENT_COLOR)
+        } else {
+            (Self::fill_color(&interaction), BORDER_COLOR)
+        };
+        self.draw_frame(rect, fill, border);
+        self.draw_centered(rect, label, TEXT_COLOR);
+        interaction.clicked
+    }
+
+    // Grip for moving or resizing things, returns how far the mouse moved while holding it
+    pub fn drag_handle(&mut self, id: &str, rect: &Aabb) -> Vec2 {
+        let interaction = self.interact(widget_id(id), rect);
+        self.draw_frame(rect, Self::fill_color(&interaction), BORDER_COLOR);
+        if interaction.active {
+            self.input.mouse_delta
+        } else {
+            Vec2::ZERO
+        }
+    }
+
+    // Check box with the label to its right, returns whether it was flipped
+    pub fn toggle(&mut self, id: &str, rect: &Aabb, label: &str, value: &mut bool) -> bool {
+        let interaction = self.interact(widget_id(id), rect);
+        if interaction.clicked {
+            *value = !*value;
+        }
+
+        let size = CAP_HEIGHT + 2;
+        let min = ivec2(rect.min.x as i32 + 1, rect.center().y as i32 - size / 2 - 1);
+        let fill = Self::fill_color(&interaction);
+        self.screen
+            .draw_rectangle(min.x, min.y, min.x + size, min.y + size, true, fill);
+        self.screen.draw_rectangle(
+            min.x,
+            min.y,
+            min.x + size,
+            min.y + size,
+            false,
+            BORDER_COLOR,
+        );
+        if *value {
+            self.screen.draw_rectangle(
+                min.x + 2,
+                min.y + 2,
+                min.x + size - 2,
+                min.y + size - 2,
+                true,
+                ACCENT_COLOR,
+            );
+        }
+        self.screen
+            .draw_str(self.font, label, min.x + size + 4, min.y + 2, TEXT_COLOR);
+        interaction.clicked
+    }
+
+    // Horizontal slider between `min` and `max`, dragging anywhere on it sets the value
+    pub fn slider(
+        &mut self,
+        id: &str,
+        rect: &Aabb,
+        label: &str,
+        value: &mut f32,
+        min: f32,
+        max: f32,
+    ) -> bool {
+        let interaction = self.interact(widget_id(id), rect);
+        let previous = *value;
+        if interaction.active {
+            let t = (self.input.mouse.x - rect.min.x) / (rect.max.x - rect.min.x - 1.0);
+            *value = min + t.clamp(0.0, 1.0) * (max - min);
+        }
+
+        self.draw_frame(rect, Self::fill_color(&interaction), BORDER_COLOR);
+        let t = ((*value - min) / (max - min)).clamp(0.0, 1.0);
+        let filled = rect.min.x as i32 + 1 + ((rect.max.x - rect.min.x - 3.0) * t) as i32;
+        self.screen.draw_rectangle(
+            rect.min.x as i32 + 1,
+            rect.max.y as i32 - 3,
+            filled,
+            rect.max.y as i32 - 2,
+            true,
+            ACCENT_COLOR,
+        );
+        self.draw_centered(rect, &format!("{} {:.2}", label, value), TEXT_COLOR);
+        *value != previous
+    }
+
+    // Scrolls `first` with the mouse wheel while the mouse is over the rectangle, and draws a
+    // scroll bar along its right edge when not all `total` rows fit in `visible` rows
+    pub fn scroll(&mut self, rect: &Aabb, first: &mut usize, total: usize, visible: usize) {
+        let max_first = total.saturating_sub(visible);
+        if rect.point_intersects(self.input.mouse) && self.input.mouse_scroll_delta.y != 0.0 {
+            let rows = self.input.mouse_scroll_delta.y.signum() as isize;
+            *first = first.saturating_add_signed(-rows);
+        }
+        *first = (*first).min(max_first);
+
+        if max_first > 0 {
+            let height = rect.max.y - rect.min.y - 2.0;
+            let bar_height = (height * visible as f32 / total as f32).max(2.0);
+            let bar_y =
+                rect.min.y + 1.0 + (height - bar_height) * (*first as f32 / max_first as f32);
+            self.screen.draw_rectangle(
+                rect.max.x as i32 - 3,
+                bar_y as i32,
+                rect.max.x as i32 - 2,
+                (bar_y + bar_height) as i32 - 1,
+                true,
+                ACCENT_COLOR,
+            );
+        }
+    }
+
+    // List of rows scrolled with the mouse wheel, `first` is the top visible row.
+    // Returns whether a row was picked
+    pub fn list(
+        &mut self,
+        id: &str,
+        rect: &Aabb,
+        items: &[&str],
+        selected: &mut usize,
+        first: &mut usize,
+    ) -> bool {
+        let interaction = self.interact(widget_id(id), rect);
+        self.draw_frame(rect, PANEL_COLOR, BORDER_COLOR);
+
+        let visible = (((rect.max.y - rect.min.y) as i32 - 2) / LIST_ROW_HEIGHT).max(0);
+        let mut picked = false;
+        for (row, item) in items.iter().enumerate().skip(*first).take(visible as usize) {
+            let y = rect.min.y + 1.0 + ((row - *first) as i32 * LIST_ROW_HEIGHT) as f32;
+            let row_rect = Aabb {
+                min: vec2(rect.min.x + 1.0, y),
+                max: vec2(rect.max.x - 1.0, y + LIST_ROW_HEIGHT as f32),
+            };
+            let row_hovered = interaction.hovered && row_rect.point_intersects(self.input.mouse);
+            if row_hovered && interaction.clicked {
+                *selected = row;
+                picked = true;
+            }
+
+            let fill = if row == *selected {
+                ACTIVE_COLOR
+            } else if row_hovered {
+                HOVER_COLOR
+            } else {
+                PANEL_COLOR
+            };
+            self.screen.draw_rectangle(
+                row_rect.min.x as i32,
+                row_rect.min.y as i32,
+                row_rect.max.x as i32 - 1,
+                row_rect.max.y as i32 - 1,
+                true,
+                fill,
+            );
+            self.screen.draw_str(
+                self.font,
+                item,
+                row_rect.min.x as i32 + 2,
+                row_rect.min.y as i32 + (LIST_ROW_HEIGHT - CAP_HEIGHT) / 2,
+                TEXT_COLOR,
+            );
+        }
+
+        self.scroll(rect, first, items.len(), visible as usize);
+        picked
+    }
+
+    // Single line of editable text, clicking it gives it the keyboard.
+    // Returns whether the text changed
+    pub fn text_field(&mut self, id: &str, rect: &Aabb, text: &mut String) -> bool {
+        let id = widget_id(id);
+        let interaction = self.interact(id, rect);
+        if interaction.clicked {
+            self.state.focused = Some(id);
+        }
+
+        let focused = self.state.focused == Some(id);
+        let mut changed = false;
+        if focused {
+            if !self.input.text_input.is_empty() {
+                text.push_str(&self.input.text_input);
+                changed = true;
+            }
+            if self.input.is_key_pressed(Key::Backspace) && text.pop().is_some() {
+                changed = true;
+            }
+        }
+
+        let border = if focused { ACCENT_COLOR } else { BORDER_COLOR };
+        self.draw_frame(rect, Self::fill_color(&interaction), border);
+
+        // Only the end of text that is too long is shown, that's where the typing happens
+        let width = (rect.max.x - rect.min.x) as i32 - 6;
+        let mut shown = text.as_str();
+        while self.font.measure_line(shown) > width {
+            let mut chars = shown.chars();
+            chars.next();
+            shown = chars.as_str();
+        }
+        let x = rect.min.x as i32 + 2;
+        let y = rect.min.y as i32 + (rect.max.y - rect.min.y) as i32 / 2 - CAP_HEIGHT / 2;
+        self.screen.draw_str(self.font, shown, x, y, TEXT_COLOR);
+
+        if focused && (self.state.time * CARET_BLINK_RATE).fract() < 0.5 {
+            let caret = x + self.font.measure_line(shown);
+            self.screen
+                .draw_rectangle(caret, y - 1, caret, y + CAP_HEIGHT, true, ACCENT_COLOR);
+        }
+        changed
+    }
+}
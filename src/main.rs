@@ -1,20 +1,45 @@
 pub mod audio;
 pub(crate) mod bitmap;
+pub(crate) mod config;
 pub(crate) mod game;
+pub(crate) mod strings;
 pub(crate) mod vulkan;
 
 use bitmap::Bitmap;
 use game::Game;
 
 use gilrs::Gilrs;
-use minifb::WindowOptions;
+use minifb::{InputCallback, WindowOptions};
 
 use crate::vulkan::init_vulkan;
 
 // Set to true to enable fullscreen mode
 const FULLSCREEN: bool = false;
 
+// Hands the characters typed into the window to the main loop
+struct TextInput(std::sync::mpsc::Sender<char>);
+
+impl InputCallback for TextInput {
+    fn add_char(&mut self, uni_char: u32) {
+        if let Some(c) = char::from_u32(uni_char) {
+            let _ = self.0.send(c);
+        }
+    }
+}
+
 fn main() {
+    // `cargo run -- --export-music <path> <seconds> [song]` renders a song to a wave file
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.get(1).map(String::as_str) == Some("--export-music") {
+        let path = args.get(2).map_or("soundtrack.wav", String::as_str);
+        let seconds = args.get(3).and_then(|s| s.parse().ok()).unwrap_or(64.0);
+        let music_path = args
+            .get(4)
+            .map_or("assets/music/level0.txt", String::as_str);
+        audio::Audio::export_music(music_path, path, seconds);
+        return;
+    }
+
     // Tell Windows not to apply unnecessary DPI scaling to this application
     #[cfg(target_os = "windows")]
     unsafe {
@@ -42,6 +67,9 @@ fn main() {
     )
     .expect("Failed to open a window :(");
 
+    let (text_sender, typed_characters) = std::sync::mpsc::channel();
+    window.set_input_callback(Box::new(TextInput(text_sender)));
+
     // Disable maximum FPS by sleeping the thread, aka we want ALL the frames
     window.set_target_fps(0);
 
@@ -164,6 +192,10 @@ fn main() {
         handle_mouse_events(minifb::MouseButton::Middle, game::MouseButton::Middle);
         handle_mouse_events(minifb::MouseButton::Right, game::MouseButton::Right);
 
+        for c in typed_characters.try_iter() {
+            game.on_char_typed(c);
+        }
+
         if window.is_key_pressed(minifb::Key::Escape, minifb::KeyRepeat::No) {
             return;
         }
@@ -191,9 +223,24 @@ fn main() {
 
         handle_key_events(minifb::Key::Equal, game::Key::EditorZoomIn);
         handle_key_events(minifb::Key::Minus, game::Key::EditorZoomOut);
+        handle_key_events(minifb::Key::C, game::Key::EditorFilterCollision);
+        handle_key_events(minifb::Key::X, game::Key::EditorFilterSpike);
+        handle_key_events(minifb::Key::V, game::Key::EditorFavourites);
+        handle_key_events(minifb::Key::F, game::Key::EditorFavourite);
+        handle_key_events(minifb::Key::C, game::Key::EditorCopy);
+        handle_key_events(minifb::Key::X, game::Key::EditorCut);
+        handle_key_events(minifb::Key::V, game::Key::EditorPaste);
+        handle_key_events(minifb::Key::Delete, game::Key::EditorDelete);
+        handle_key_events(minifb::Key::H, game::Key::EditorFlipX);
+        handle_key_events(minifb::Key::J, game::Key::EditorFlipY);
+        handle_key_events(minifb::Key::R, game::Key::EditorRotate);
+        handle_key_events(minifb::Key::P, game::Key::EditorPlay);
+
+        handle_key_events(minifb::Key::F1, game::Key::Settings);
 
         handle_key_events(minifb::Key::Key1, game::Key::Key1);
         handle_key_events(minifb::Key::Key2, game::Key::Key2);
+        handle_key_events(minifb::Key::Key3, game::Key::Key3);
 
         // Toggle masks
         handle_key_events(minifb::Key::R, game::Key::MaskRed);
@@ -218,6 +265,15 @@ fn main() {
         handle_key_events(minifb::Key::L, game::Key::MusicD4);
         handle_key_events(minifb::Key::P, game::Key::MusicDs4);
         handle_key_events(minifb::Key::Semicolon, game::Key::MusicE4);
+        handle_key_events(minifb::Key::Comma, game::Key::MusicOctaveDown);
+        handle_key_events(minifb::Key::Period, game::Key::MusicOctaveUp);
+        handle_key_events(minifb::Key::Tab, game::Key::MusicWave);
+        handle_key_events(minifb::Key::LeftShift, game::Key::MusicSustain);
+        handle_key_events(minifb::Key::F5, game::Key::MusicRecord);
+        handle_key_events(minifb::Key::Backspace, game::Key::MusicClear);
+        handle_key_events(minifb::Key::F6, game::Key::MusicExport);
+
+        handle_key_events(minifb::Key::Backspace, game::Key::Backspace);
 
         let mut bitmap = if let Some(vulkan_state) = &mut vulkan_state {
             vulkan_state.acquire_bitmap()
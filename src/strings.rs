@@ -0,0 +1,103 @@
+// Translated text, one `key = text` file per language in assets/lang, see assets/lang/en.txt
+use crate::config::{CONFIG_PATH, Config};
+
+const LANGUAGE_DIRECTORY: &str = "assets/lang";
+const DEFAULT_LANGUAGE: &str = "en";
+
+fn parse(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_owned(), value.trim().replace("\\n", "\n")))
+        .collect()
+}
+
+fn load_table(language: &str) -> Vec<(String, String)> {
+    let path = format!("{}/{}.txt", LANGUAGE_DIRECTORY, language);
+    let text = std::fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("Failed to load strings: \"{}\". Error: {}", path, err));
+    parse(&text)
+}
+
+pub struct Strings {
+    pub language: String,
+    entries: Vec<(String, String)>,
+    fallback: Vec<(String, String)>, // the default language, for keys that are not translated
+    languages: Vec<(String, String)>, // file name and the name the language calls itself
+}
+
+impl Strings {
+    // Starts with the language from the config file
+    pub fn new() -> Self {
+        let mut codes = std::fs::read_dir(LANGUAGE_DIRECTORY)
+            .expect("Failed to read assets/lang")
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|e| e == "txt"))
+            .map(|path| path.file_stem().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        codes.sort();
+        let languages = codes
+            .into_iter()
+            .map(|code| {
+                let name = load_table(&code)
+                    .into_iter()
+                    .find(|(key, _)| key == "language")
+                    .map_or_else(|| code.clone(), |(_, name)| name);
+                (code, name)
+            })
+            .collect::<Vec<_>>();
+
+        let language = Config::from_file(CONFIG_PATH)
+            .get::<String>("language")
+            .filter(|language| languages.iter().any(|(code, _)| code == language))
+            .unwrap_or_else(|| DEFAULT_LANGUAGE.to_owned());
+
+        let fallback = load_table(DEFAULT_LANGUAGE);
+        let entries = if language == DEFAULT_LANGUAGE {
+            fallback.clone()
+        } else {
+            load_table(&language)
+        };
+
+        Self {
+            language,
+            entries,
+            fallback,
+            languages,
+        }
+    }
+
+    // Missing keys show up as the key itself so they are easy to spot
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        self.entries
+            .iter()
+            .chain(self.fallback.iter())
+            .find(|(k, _)| k == key)
+            .map_or(key, |(_, text)| text.as_str())
+    }
+
+    // Names of the languages, in the order `set_language` takes them
+    pub fn language_names(&self) -> Vec<&str> {
+        self.languages
+            .iter()
+            .map(|(_, name)| name.as_str())
+            .collect()
+    }
+
+    pub fn language_index(&self) -> usize {
+        self.languages
+            .iter()
+            .position(|(code, _)| *code == self.language)
+            .unwrap_or(0)
+    }
+
+    // Switches to another language and stores it in the config file
+    pub fn set_language(&mut self, index: usize) {
+        self.language = self.languages[index].0.clone();
+        self.entries = load_table(&self.language);
+
+        let mut config = Config::from_file(CONFIG_PATH);
+        config.set("language", &self.language);
+        config.store_to_file(CONFIG_PATH);
+    }
+}